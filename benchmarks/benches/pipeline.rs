@@ -0,0 +1,364 @@
+//! Benchmarks of the code `register_components!` generates, on worlds of 100, 1k and 10k
+//! entities with a point, an extent and a grid spatial component and a secondary index
+//! by team. They live in a crate of their own so the macro sees the features of a game
+//! crate rather than those of `rule-system`.
+//!
+//! To catch regressions when the macro output changes, save a baseline before the change
+//! and compare against it after:
+//!
+//!     cargo bench -p rule-system-benchmarks -- --save-baseline before
+//!     cargo bench -p rule-system-benchmarks -- --baseline before
+//!
+//! Criterion reports each benchmark that regressed beyond its noise threshold.
+//! `cargo test -p rule-system-benchmarks --benches` runs every benchmark once, as a
+//! smoke test.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rule_system::fuzz::Rng;
+use rule_system::register_components;
+
+pub type EntityId = u32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Health(pub i32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Team(pub u8);
+
+pub type Position = [i32; 2];
+pub type Tile = [i32; 2];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Footprint {
+    pub origin: [i32; 2],
+    pub size: [i32; 2],
+}
+
+impl rstar::RTreeObject for Footprint {
+    type Envelope = rstar::AABB<[i32; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        rstar::AABB::from_corners(
+            self.origin,
+            [self.origin[0] + self.size[0] - 1, self.origin[1] + self.size[1] - 1],
+        )
+    }
+}
+
+mod world {
+    use super::*;
+    use rstar::Point;
+
+    register_components!(
+        index EntityId,
+        components { Health, Team }
+        spatial { Position, Footprint: extent, Tile: grid }
+        indexes { by_team: Team => u8 = |team: &Team| team.0 }
+    );
+}
+
+use world::*;
+
+const SIZES: [u32; 3] = [100, 1_000, 10_000];
+const BATCH: u32 = 100;
+
+#[derive(Debug, Clone)]
+enum Input {
+    Move(EntityId, Position),
+    Damage(EntityId, i32),
+    Heal(EntityId),
+}
+
+fn populate(
+    input: Input,
+    state: &GameState,
+    action: &mut Action,
+    _: &PositionIndex,
+    _: &FootprintIndex,
+    _: &TileIndex,
+) {
+    match input {
+        Input::Move(id, position) => {
+            action.insert_position(id, position);
+            action.insert_footprint(id, Footprint { origin: position, size: [2, 2] });
+            action.insert_tile(id, position);
+        }
+        Input::Damage(id, amount) => {
+            let health = state.get_health(id).map_or(0, |health| health.0);
+            action.insert_health(id, Health(health - amount));
+        }
+        Input::Heal(id) => action.insert_health(id, Health(100)),
+    }
+}
+
+fn in_bounds(
+    action: &Action,
+    _: &GameState,
+    _: &PositionIndex,
+    _: &FootprintIndex,
+    _: &TileIndex,
+) -> (ActionStatus, RuleStatus, Vec<Input>) {
+    if action.get_updated_position().values().all(|&[x, y]| x.abs() <= 1_000 && y.abs() <= 1_000) {
+        (ActionStatus::Accept, RuleStatus::KeepChecking, Vec::new())
+    } else {
+        (ActionStatus::Reject, RuleStatus::StopChecking, Vec::new())
+    }
+}
+
+fn no_stacking(
+    action: &Action,
+    state: &GameState,
+    positions: &PositionIndex,
+    _: &FootprintIndex,
+    _: &TileIndex,
+) -> (ActionStatus, RuleStatus, Vec<Input>) {
+    let future = FutureState { state, action };
+    for (id, position) in action.get_updated_position() {
+        if future.entities_at_position(positions, position).iter().any(|other| other != id) {
+            return (ActionStatus::Reject, RuleStatus::KeepChecking, Vec::new());
+        }
+    }
+    (ActionStatus::Accept, RuleStatus::KeepChecking, Vec::new())
+}
+
+fn revive(
+    action: &Action,
+    _: &GameState,
+    _: &PositionIndex,
+    _: &FootprintIndex,
+    _: &TileIndex,
+) -> (ActionStatus, RuleStatus, Vec<Input>) {
+    let fallen = action
+        .get_updated_health()
+        .iter()
+        .filter(|(_, health)| health.0 <= 0)
+        .map(|(&id, _)| Input::Heal(id))
+        .collect();
+    (ActionStatus::Accept, RuleStatus::KeepChecking, fallen)
+}
+
+fn count_moves(
+    events: &mut std::collections::VecDeque<EntityId>,
+    action: &Action,
+    _: &GameState,
+    _: &PositionIndex,
+    _: &FootprintIndex,
+    _: &TileIndex,
+) {
+    events.extend(action.get_updated_position().keys());
+}
+
+/// Entities on a grid with a free cell between neighbours, so moves rarely collide.
+fn world(entities: u32) -> GameWorld<Input, EntityId> {
+    let side = (entities as f64).sqrt().ceil() as u32;
+    let mut state = GameState::new();
+    let mut action = Action::new();
+    for id in 0..entities {
+        let position = [(id % side) as i32 * 3, (id / side) as i32 * 3];
+        action.insert_position(id, position);
+        action.insert_footprint(id, Footprint { origin: position, size: [2, 2] });
+        action.insert_tile(id, position);
+        action.insert_health(id, Health(100));
+        action.insert_team(id, Team((id % 4) as u8));
+    }
+    state.commit_action(&mut action);
+    GameWorld::new_with_initial_state(
+        vec![in_bounds, no_stacking, revive],
+        populate,
+        vec![count_moves],
+        vec![],
+        vec![],
+        state,
+    )
+}
+
+fn random_position(rng: &mut Rng) -> Position {
+    [rng.range(-1_000..1_000) as i32, rng.range(-1_000..1_000) as i32]
+}
+
+fn random_input(rng: &mut Rng, entities: u32) -> Input {
+    let id = rng.below(entities as u64) as EntityId;
+    if rng.chance(0.7) {
+        Input::Move(id, random_position(rng))
+    } else {
+        Input::Damage(id, rng.range(1..60) as i32)
+    }
+}
+
+fn process_actions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_actions");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for entities in SIZES {
+        let mut world = world(entities);
+        let mut rng = Rng::new(1);
+        group.bench_function(BenchmarkId::from_parameter(entities), |b| {
+            b.iter(|| {
+                for _ in 0..BATCH {
+                    world.enqueue_action(random_input(&mut rng, entities));
+                }
+                world.process_actions();
+                world.events_queue.clear();
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Updates of `BATCH` existing entities, so the state keeps its size.
+fn moves(rng: &mut Rng, entities: u32) -> Action {
+    let mut action = Action::new();
+    for _ in 0..BATCH {
+        let id = rng.below(entities as u64) as EntityId;
+        let position = random_position(rng);
+        action.insert_position(id, position);
+        action.insert_footprint(id, Footprint { origin: position, size: [2, 2] });
+        action.insert_tile(id, position);
+        action.insert_health(id, Health(rng.range(1..100) as i32));
+        action.insert_team(id, Team(rng.below(4) as u8));
+    }
+    action
+}
+
+/// Commits to a bare `GameState`, which keeps its secondary index but has no spatial
+/// indexes that would drift from it.
+fn commit_action(c: &mut Criterion) {
+    let mut group = c.benchmark_group("commit_action");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for entities in SIZES {
+        let mut state = world(entities).state;
+        let mut rng = Rng::new(2);
+        group.bench_function(BenchmarkId::from_parameter(entities), |b| {
+            b.iter_batched(
+                || moves(&mut rng, entities),
+                |mut action| state.commit_action(&mut action),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// `apply_action` updates the spatial indexes and then commits; compare with
+/// `commit_action` for the cost of the indexes alone.
+fn spatial_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spatial_update");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for entities in SIZES {
+        let mut world = world(entities);
+        let mut rng = Rng::new(3);
+        group.bench_function(BenchmarkId::from_parameter(entities), |b| {
+            b.iter_batched(|| moves(&mut rng, entities), |action| world.apply_action(action), BatchSize::SmallInput)
+        });
+    }
+    group.finish();
+}
+
+/// Lookups through a pending action of `BATCH` updates, half of them hitting the action
+/// and half falling through to the state.
+fn future_state(c: &mut Criterion) {
+    let mut group = c.benchmark_group("future_state");
+    group.throughput(Throughput::Elements(2 * BATCH as u64));
+    for entities in SIZES {
+        let world = world(entities);
+        let action = moves(&mut Rng::new(4), entities);
+        let future = FutureState { state: &world.state, action: &action };
+        let ids: Vec<EntityId> = action
+            .get_updated_position()
+            .keys()
+            .copied()
+            .chain((0..entities).step_by((entities / BATCH) as usize))
+            .collect();
+        group.bench_function(BenchmarkId::new("get", entities), |b| {
+            b.iter(|| {
+                for &id in &ids {
+                    black_box(future.get_health(black_box(id)));
+                    black_box(future.get_position(black_box(id)));
+                }
+            })
+        });
+        group.bench_function(BenchmarkId::new("entities_within", entities), |b| {
+            b.iter(|| {
+                for &id in &ids {
+                    let center = future.get_position(id).copied().unwrap_or_default();
+                    black_box(future.entities_within_position(&world.spatial_position, &center, 10));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Queries of the `: grid` component around `BATCH` entities.
+fn grid_queries(c: &mut Criterion) {
+    let mut group = c.benchmark_group("grid_queries");
+    group.throughput(Throughput::Elements(BATCH as u64));
+    for entities in SIZES {
+        let world = world(entities);
+        let tiles: Vec<Tile> = (0..entities)
+            .step_by((entities / BATCH) as usize)
+            .map(|id| world.state.get_tile(id).copied().unwrap_or_default())
+            .collect();
+        group.bench_function(BenchmarkId::new("entity_at", entities), |b| {
+            b.iter(|| {
+                for tile in &tiles {
+                    black_box(world.entity_at_tile(black_box(tile)));
+                }
+            })
+        });
+        group.bench_function(BenchmarkId::new("entities_within", entities), |b| {
+            b.iter(|| {
+                for tile in &tiles {
+                    black_box(world.entities_within_tile(black_box(tile), 10));
+                }
+            })
+        });
+        group.bench_function(BenchmarkId::new("nearest_n", entities), |b| {
+            b.iter(|| {
+                for tile in &tiles {
+                    black_box(world.nearest_n_tile(black_box(tile), 5));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Lookups of every team in the `by_team` index, committed and through a pending action
+/// of `BATCH` updates.
+fn secondary_index(c: &mut Criterion) {
+    let mut group = c.benchmark_group("secondary_index");
+    group.throughput(Throughput::Elements(4));
+    for entities in SIZES {
+        let world = world(entities);
+        let action = moves(&mut Rng::new(5), entities);
+        let future = FutureState { state: &world.state, action: &action };
+        group.bench_function(BenchmarkId::new("state", entities), |b| {
+            b.iter(|| {
+                for team in 0..4 {
+                    black_box(world.state.by_team(black_box(&team)).count());
+                }
+            })
+        });
+        group.bench_function(BenchmarkId::new("future", entities), |b| {
+            b.iter(|| {
+                for team in 0..4 {
+                    black_box(future.by_team(black_box(&team)));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    process_actions,
+    commit_action,
+    spatial_update,
+    future_state,
+    grid_queries,
+    secondary_index
+);
+criterion_main!(benches);
@@ -0,0 +1 @@
+//! Benchmarks of `register_components!`, in `benches/`.
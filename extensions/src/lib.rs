@@ -0,0 +1,62 @@
+//! Tests of the scripting and plugin support of `register_components!`, in `tests/`.
+
+use std::collections::VecDeque;
+
+use rule_system::register_components;
+#[cfg(feature = "serde_support")]
+use serde::{Deserialize, Serialize};
+
+pub type EntityId = u32;
+pub type Position = [i32; 2];
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct Health(pub i32);
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct Team(pub u8);
+
+pub mod world {
+    use rstar::Point;
+
+    use super::*;
+
+    register_components!(
+        index EntityId,
+        components { Health, Team }
+        spatial { Position }
+    );
+}
+
+pub use world::*;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub enum Act {
+    Spawn { id: EntityId, at: Position, team: u8 },
+    Move(EntityId, Position),
+    Hit(EntityId, i32),
+}
+
+/// Fills in nothing, for worlds whose actions are populated by a script or a plugin.
+pub fn populate_nothing(_: Act, _: &GameState, _: &mut Action, _: &PositionIndex) {}
+
+/// Records who issued each accepted action.
+pub fn issuers(events: &mut VecDeque<String>, action: &Action, _: &GameState, _: &PositionIndex) {
+    events.push_back(format!("by {}", action.issuer()));
+}
+
+/// A world with the units spawned directly, without running any rules or keeping any events.
+pub fn world_with(units: &[(EntityId, Position, u8)]) -> GameWorld<Act, String> {
+    let mut world = GameWorld::new(vec![], populate_nothing, vec![issuers], vec![], vec![]);
+    let mut action = Action::new();
+    for &(id, at, team) in units {
+        action.insert_health(id, Health(10));
+        action.insert_team(id, Team(team));
+        action.insert_position(id, at);
+    }
+    world.apply_action(action);
+    world.events_queue.clear();
+    world
+}
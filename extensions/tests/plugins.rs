@@ -0,0 +1,106 @@
+#![cfg(feature = "plugins")]
+
+use rule_system::actor::Issuer;
+use rule_system::plugins::{Plugin, PluginError};
+use rule_system_extensions::*;
+
+// Where the host writes arguments, past the strings below.
+const ARGUMENTS: usize = 4096;
+
+/// Lays `strings` out from address 0, returning the data segments and each string's
+/// result, `ptr << 32 | len`.
+fn layout(strings: &[&str]) -> (String, Vec<u64>) {
+    let mut segments = String::new();
+    let mut results = Vec::new();
+    let mut offset = 0;
+    for string in strings {
+        segments.push_str(&format!("(data (i32.const {}) \"{}\")\n", offset, string.replace('"', "\\\"")));
+        results.push((offset as u64) << 32 | string.len() as u64);
+        offset += string.len();
+    }
+    assert!(offset <= ARGUMENTS);
+    (segments, results)
+}
+
+// Moves unit 1, hits it below zero, which the rule rejects, or changes its team, which
+// makes the rule trap. Each export finds out which by searching its argument.
+fn plugin() -> Plugin {
+    let empty_removals = r#""removals":{"health":[],"team":[],"position":[]}"#;
+    let (segments, results) = layout(&[
+        "Move",
+        "Hit",
+        &format!(r#"{{"updates":{{"health":{{}},"team":{{}},"position":{{"1":[1,1]}}}},{}}}"#, empty_removals),
+        &format!(r#"{{"updates":{{"health":{{"1":-5}},"team":{{}},"position":{{}}}},{}}}"#, empty_removals),
+        &format!(r#"{{"updates":{{"health":{{}},"team":{{"1":9}},"position":{{}}}},{}}}"#, empty_removals),
+        r#""health":{"1":-5}"#,
+        r#""team":{"1":9}"#,
+        r#"{"accept":false}"#,
+        r#"{"accept":true}"#,
+    ]);
+    let [find_move, find_hit, moved, hit, recruited, find_hit_result, find_recruited, reject, accept] =
+        <[u64; 9]>::try_from(results).unwrap();
+    let needle = |result: u64| format!("(i32.const {}) (i32.const {})", result >> 32, result as u32);
+    let wat = format!(
+        r#"(module
+            (memory (export "memory") 1)
+            {segments}
+            (func (export "alloc") (param i32) (result i32) (i32.const {ARGUMENTS}))
+            (func $contains (param $at i32) (param $len i32) (param $needle i32) (param $needle_len i32) (result i32)
+                (local $i i32) (local $j i32)
+                (block $absent
+                    (loop $next
+                        (br_if $absent (i32.gt_u (i32.add (local.get $i) (local.get $needle_len)) (local.get $len)))
+                        (local.set $j (i32.const 0))
+                        (block $mismatch
+                            (loop $compare
+                                (if (i32.eq (local.get $j) (local.get $needle_len)) (then (return (i32.const 1))))
+                                (br_if $mismatch (i32.ne
+                                    (i32.load8_u (i32.add (local.get $at) (i32.add (local.get $i) (local.get $j))))
+                                    (i32.load8_u (i32.add (local.get $needle) (local.get $j)))))
+                                (local.set $j (i32.add (local.get $j) (i32.const 1)))
+                                (br $compare)))
+                        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+                        (br $next)))
+                (i32.const 0))
+            (func (export "populate") (param $at i32) (param $len i32) (result i64)
+                (if (call $contains (local.get $at) (local.get $len) {find_move}) (then (return (i64.const {moved}))))
+                (if (call $contains (local.get $at) (local.get $len) {find_hit}) (then (return (i64.const {hit}))))
+                (i64.const {recruited}))
+            (func (export "rule") (param $at i32) (param $len i32) (result i64)
+                (if (call $contains (local.get $at) (local.get $len) {find_recruited}) (then unreachable))
+                (if (call $contains (local.get $at) (local.get $len) {find_hit_result}) (then (return (i64.const {reject}))))
+                (i64.const {accept})))"#,
+        find_move = needle(find_move),
+        find_hit = needle(find_hit),
+        find_hit_result = needle(find_hit_result),
+        find_recruited = needle(find_recruited),
+    );
+    Plugin::load(&wat::parse_str(wat).unwrap()).unwrap()
+}
+
+#[test]
+fn plugins_populate_and_check_actions() {
+    let mut world = world_with(&[(1, [0, 0], 1)]);
+    let plugin = plugin();
+    world.set_populate_plugin(plugin.clone());
+    world.add_plugin_rule(plugin.clone().with_components(&["health"]));
+
+    world.enqueue_action_as(Issuer::Player(1), Act::Move(1, [7, 7]));
+    world.process_actions();
+    assert_eq!(world.state.get_position(1), Some(&[1, 1]));
+    assert_eq!(world.entity_at_position(&[1, 1]), Some(1));
+    assert_eq!(world.events_queue, ["by player 1"]);
+
+    world.enqueue_action(Act::Hit(1, 15));
+    world.process_actions();
+    assert_eq!(world.state.get_health(1), Some(&Health(10)));
+    assert!(plugin.take_errors().is_empty());
+
+    world.enqueue_action(Act::Spawn { id: 1, at: [0, 0], team: 9 });
+    world.process_actions();
+    assert_eq!(world.state.get_team(1), Some(&Team(1)));
+    let errors = plugin.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(errors[0], PluginError::Trap(_)), "{:?}", errors);
+    assert_eq!(world.events_queue, ["by player 1"]);
+}
@@ -0,0 +1,131 @@
+#![cfg(feature = "scripting")]
+
+use rule_system::actor::Issuer;
+use rule_system::scripting::{Script, ScriptError};
+use rule_system_extensions::*;
+
+const POPULATE: &str = r#"
+    fn populate(input, state, action) {
+        if "Spawn" in input {
+            let unit = input.Spawn;
+            action.set_component("health", unit.id, 10);
+            action.set_component("team", unit.id, unit.team);
+            action.set_component("position", unit.id, unit.at);
+        } else if "Move" in input {
+            action.set_component("position", input.Move[0], input.Move[1]);
+        } else {
+            let target = input.Hit[0];
+            let health = state.get_component("health", target);
+            if health != () {
+                action.set_component("health", target, health - input.Hit[1]);
+            }
+        }
+        action
+    }
+"#;
+
+fn scripted_world() -> GameWorld<Act, String> {
+    let mut world = world_with(&[(1, [0, 0], 1), (2, [5, 5], 2)]);
+    world.set_populate_script(Script::compile(POPULATE).unwrap()).unwrap();
+    world
+}
+
+#[test]
+fn populate_scripts_fill_in_actions_for_their_issuer() {
+    let mut world = scripted_world();
+    world.enqueue_action_as(Issuer::Player(1), Act::Move(1, [2, 3]));
+    world.enqueue_action_as(Issuer::Player(2), Act::Hit(1, 4));
+    world.enqueue_action(Act::Spawn { id: 3, at: [7, 7], team: 2 });
+    world.process_actions();
+
+    assert_eq!(world.state.get_position(1), Some(&[2, 3]));
+    assert_eq!(world.entity_at_position(&[2, 3]), Some(1));
+    assert_eq!(world.state.get_health(1), Some(&Health(6)));
+    assert_eq!(world.state.get_team(3), Some(&Team(2)));
+    assert_eq!(world.entity_at_position(&[7, 7]), Some(3));
+    assert_eq!(world.events_queue, ["by player 1", "by player 2", "by system"]);
+}
+
+#[test]
+fn rule_scripts_decide_and_react_with_follow_on_actions() {
+    let mut world = scripted_world();
+    let rule = Script::compile(
+        r#"
+        fn rule(action, state) {
+            let health = future_component(state, action, "health", 1);
+            if health == () || health > 0 {
+                return true;
+            }
+            #{ accept: false, actions: [#{ Move: [1, [9, 9]] }] }
+        }
+        "#,
+    )
+    .unwrap();
+    world.add_script_rule(rule.clone()).unwrap();
+
+    world.enqueue_action_as(Issuer::Player(2), Act::Hit(1, 4));
+    world.enqueue_action_as(Issuer::Player(2), Act::Hit(1, 20));
+    world.process_actions();
+
+    assert_eq!(world.state.get_health(1), Some(&Health(6)));
+    assert_eq!(world.state.get_position(1), Some(&[9, 9]));
+    // The follow-on move keeps the issuer of the hit that caused it.
+    assert_eq!(world.events_queue, ["by player 2", "by player 2"]);
+    assert!(rule.take_errors().is_empty());
+}
+
+#[test]
+fn failing_scripts_reject_the_action_and_keep_the_error() {
+    let mut world = scripted_world();
+    let rule = Script::compile(r#"fn rule(action, state) { throw "no moving" }"#).unwrap();
+    world.add_script_rule(rule.clone()).unwrap();
+
+    world.enqueue_action(Act::Move(1, [1, 1]));
+    world.process_actions();
+
+    assert_eq!(world.state.get_position(1), Some(&[0, 0]));
+    let errors = rule.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(&errors[0], ScriptError::Runtime(error) if error.contains("no moving")), "{:?}", errors);
+}
+
+#[test]
+fn scripts_only_see_the_components_they_ask_for() {
+    let mut world = scripted_world();
+    // Accepts only while it can see both the health and the team of the unit.
+    let rule = r#"fn rule(action, state) { "team" in state && state.get_component("health", 1) == 10 }"#;
+    world.add_script_rule(Script::compile(rule).unwrap().with_components(&["health", "position"])).unwrap();
+
+    world.enqueue_action(Act::Move(1, [1, 1]));
+    world.process_actions();
+    assert_eq!(world.state.get_position(1), Some(&[0, 0]));
+
+    let mut world = scripted_world();
+    world.add_script_rule(Script::compile(rule).unwrap().with_components(&["health", "team"])).unwrap();
+    world.enqueue_action(Act::Move(1, [1, 1]));
+    world.process_actions();
+    assert_eq!(world.state.get_position(1), Some(&[1, 1]));
+}
+
+#[test]
+fn hook_scripts_emit_events() {
+    let mut world = scripted_world();
+    let hook = r#"
+        fn hook(action, state) {
+            let events = [];
+            for id in action.updates.health.keys() {
+                events.push(`${id} was at ${state.get_component("health", id)}`);
+            }
+            events
+        }
+    "#;
+    world.add_script_hook_on_accepted(Script::compile(hook).unwrap()).unwrap();
+    world.add_script_hook_on_rejected(Script::compile(r#"fn hook(action, state) { ["rejected"] }"#).unwrap()).unwrap();
+    world.add_script_rule(Script::compile(r#"fn rule(action, state) { !("Hit" in action) && action.updates.position.len() == 0 }"#).unwrap()).unwrap();
+
+    world.enqueue_action(Act::Hit(2, 3));
+    world.enqueue_action(Act::Move(2, [1, 1]));
+    world.process_actions();
+
+    assert_eq!(world.events_queue, ["by system", "2 was at 10", "rejected"]);
+}
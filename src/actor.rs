@@ -0,0 +1,62 @@
+use std::fmt;
+
+#[cfg(feature = "tcp")]
+use serde::{Deserialize, Serialize};
+
+pub type PlayerId = u32;
+
+/// Who enqueued an action. Follow-up actions created by rules inherit the issuer of the
+/// action that caused them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "tcp", derive(Serialize, Deserialize))]
+pub enum Issuer {
+    /// The game itself. Trusted, so permissions are not checked.
+    #[default]
+    System,
+    Player(PlayerId),
+    /// A computer opponent playing as the given player.
+    Ai(PlayerId),
+}
+
+impl Issuer {
+    /// The player this issuer acts for, if any.
+    pub fn player(&self) -> Option<PlayerId> {
+        match self {
+            Issuer::System => None,
+            Issuer::Player(player) | Issuer::Ai(player) => Some(*player),
+        }
+    }
+
+    /// Whether this issuer may act on a unit owned by `owner`. Only the system may act on
+    /// units nobody owns.
+    pub fn controls(&self, owner: Option<PlayerId>) -> bool {
+        match self {
+            Issuer::System => true,
+            _ => owner.is_some() && owner == self.player(),
+        }
+    }
+}
+
+impl fmt::Display for Issuer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issuer::System => write!(f, "system"),
+            Issuer::Player(player) => write!(f, "player {}", player),
+            Issuer::Ai(player) => write!(f, "AI for player {}", player),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issuers_control_their_own_units() {
+        assert!(Issuer::Player(1).controls(Some(1)));
+        assert!(Issuer::Ai(2).controls(Some(2)));
+        assert!(!Issuer::Player(1).controls(Some(2)));
+        assert!(!Issuer::Player(1).controls(None));
+        assert!(Issuer::System.controls(None));
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+
+use crate::actor::Issuer;
+
+/// The rule that created a follow-on action.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cause {
+    /// The `id` of the action the rule was checking.
+    pub parent: u64,
+    pub rule: usize,
+    /// Whether the rule accepted that action, which decides the queue the follow-on went to.
+    pub rule_accepted: bool,
+}
+
+/// One processed action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CascadeNode {
+    /// Unique for the lifetime of the world, so causes stay valid across cascades.
+    pub id: u64,
+    /// The action input, formatted with `Debug`.
+    pub input: String,
+    pub issuer: Issuer,
+    pub accepted: bool,
+    /// The first rule that rejected the action.
+    pub rejected_by: Option<usize>,
+    /// `None` for actions enqueued from outside the rules.
+    pub cause: Option<Cause>,
+}
+
+/// The causal tree of the actions processed by a `process_actions` call, recorded once
+/// `GameWorld::set_cascade_recording` is enabled. Nodes are in processing order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cascade {
+    pub nodes: Vec<CascadeNode>,
+}
+
+impl Cascade {
+    pub fn node(&self, id: u64) -> Option<&CascadeNode> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    /// Actions enqueued from outside the rules, or caused by actions of an earlier cascade.
+    pub fn roots(&self) -> impl Iterator<Item = &CascadeNode> {
+        let ids = self.ids();
+        self.nodes
+            .iter()
+            .filter(move |node| node.cause.is_none_or(|cause| !ids.contains(&cause.parent)))
+    }
+
+    pub fn children(&self, id: u64) -> impl Iterator<Item = &CascadeNode> {
+        self.nodes
+            .iter()
+            .filter(move |node| node.cause.is_some_and(|cause| cause.parent == id))
+    }
+
+    /// The longest chain of actions causing one another.
+    pub fn depth(&self) -> usize {
+        let mut children: HashMap<u64, Vec<&CascadeNode>> = HashMap::new();
+        for node in &self.nodes {
+            if let Some(cause) = node.cause {
+                children.entry(cause.parent).or_default().push(node);
+            }
+        }
+        let mut stack: Vec<(&CascadeNode, usize)> = self.roots().map(|root| (root, 1)).collect();
+        let mut deepest = 0;
+        while let Some((node, depth)) = stack.pop() {
+            deepest = deepest.max(depth);
+            stack.extend(children.get(&node.id).into_iter().flatten().map(|child| (*child, depth + 1)));
+        }
+        deepest
+    }
+
+    fn ids(&self) -> HashSet<u64> {
+        self.nodes.iter().map(|node| node.id).collect()
+    }
+
+    /// Graphviz DOT: accepted actions in green, rejected ones in red, and edges labelled
+    /// with the rule that created the follow-on.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cascade {\n    node [shape=box];\n");
+        for node in &self.nodes {
+            let verdict = match (node.accepted, node.rejected_by) {
+                (true, _) => "accepted".to_string(),
+                (false, Some(rule)) => format!("rejected by rule {}", rule),
+                (false, None) => "rejected".to_string(),
+            };
+            let _ = writeln!(
+                dot,
+                "    n{} [label=\"{}\\n{} ({})\", color={}];",
+                node.id,
+                escape_dot(&node.input),
+                verdict,
+                node.issuer,
+                if node.accepted { "darkgreen" } else { "red" },
+            );
+        }
+        let ids = self.ids();
+        for node in &self.nodes {
+            if let Some(cause) = node.cause.filter(|cause| ids.contains(&cause.parent)) {
+                let _ = writeln!(
+                    dot,
+                    "    n{} -> n{} [label=\"rule {}\"{}];",
+                    cause.parent,
+                    node.id,
+                    cause.rule,
+                    if cause.rule_accepted { "" } else { ", style=dashed" },
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// A JSON object with the `nodes` in processing order, each linking to its `parent`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"nodes\":[");
+        for (index, node) in self.nodes.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+            let _ = write!(json, "{{\"id\":{},\"input\":", node.id);
+            write_json_string(&mut json, &node.input);
+            json.push_str(",\"issuer\":");
+            write_json_string(&mut json, &node.issuer.to_string());
+            let _ = write!(
+                json,
+                ",\"accepted\":{},\"rejected_by\":{},\"parent\":{},\"rule\":{},\"rule_accepted\":{}}}",
+                node.accepted,
+                json_option(node.rejected_by),
+                json_option(node.cause.map(|cause| cause.parent)),
+                json_option(node.cause.map(|cause| cause.rule)),
+                json_option(node.cause.map(|cause| cause.rule_accepted)),
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+fn json_option<V: ToString>(value: Option<V>) -> String {
+    value.map_or_else(|| "null".to_string(), |value| value.to_string())
+}
+
+/// Writes `text` as a JSON string literal, escaping what RFC 8259 requires.
+fn write_json_string(json: &mut String, text: &str) {
+    json.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            character if character.is_control() => {
+                let _ = write!(json, "\\u{:04x}", character as u32);
+            }
+            character => json.push(character),
+        }
+    }
+    json.push('"');
+}
+
+/// Escapes a string for a DOT string literal, where `\n` is a line break. DOT has no
+/// escapes for the other control characters, so they become spaces.
+fn escape_dot(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            character if character.is_control() => escaped.push(' '),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: u64, input: &str, accepted: bool, cause: Option<Cause>) -> CascadeNode {
+        CascadeNode {
+            id,
+            input: input.to_string(),
+            issuer: Issuer::System,
+            accepted,
+            rejected_by: (!accepted).then_some(0),
+            cause,
+        }
+    }
+
+    #[test]
+    fn cascades_export_their_causal_tree() {
+        let cascade = Cascade {
+            nodes: vec![
+                node(4, "Explode(\"barrel\")", true, None),
+                node(5, "Kill(2)", true, Some(Cause { parent: 4, rule: 1, rule_accepted: true })),
+                node(6, "Explode(2)", false, Some(Cause { parent: 5, rule: 0, rule_accepted: true })),
+                node(7, "Kill(3)", true, Some(Cause { parent: 2, rule: 0, rule_accepted: true })),
+            ],
+        };
+
+        assert_eq!(cascade.roots().map(|node| node.id).collect::<Vec<_>>(), vec![4, 7]);
+        assert_eq!(cascade.depth(), 3);
+
+        let dot = cascade.to_dot();
+        assert!(dot.contains("n4 [label=\"Explode(\\\"barrel\\\")\\naccepted (system)\", color=darkgreen];"));
+        assert!(dot.contains("n6 [label=\"Explode(2)\\nrejected by rule 0 (system)\", color=red];"));
+        assert!(dot.contains("n4 -> n5 [label=\"rule 1\"];"));
+        assert!(!dot.contains("n2 -> n7"));
+
+        let json = cascade.to_json();
+        assert!(json.starts_with("{\"nodes\":[{\"id\":4,\"input\":\"Explode(\\\"barrel\\\")\",\"issuer\":\"system\",\"accepted\":true,\"rejected_by\":null,\"parent\":null"));
+        assert!(json.contains("{\"id\":5,\"input\":\"Kill(2)\",\"issuer\":\"system\",\"accepted\":true,\"rejected_by\":null,\"parent\":4,\"rule\":1,\"rule_accepted\":true}"));
+    }
+
+    #[test]
+    fn long_chains_are_measured_without_recursing() {
+        let mut nodes = vec![node(0, "Kill(0)", true, None)];
+        nodes.extend((1..100_000).map(|id| {
+            node(id, "Kill(1)", true, Some(Cause { parent: id - 1, rule: 0, rule_accepted: true }))
+        }));
+        let cascade = Cascade { nodes };
+
+        assert_eq!(cascade.roots().count(), 1);
+        assert_eq!(cascade.depth(), 100_000);
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn json_escapes_every_input() {
+        let input = "Say(\"a\\b\n\t\r\u{1}\u{7f}é\")";
+        let cascade = Cascade { nodes: vec![node(0, input, true, None)] };
+
+        let json: serde_json::Value = serde_json::from_str(&cascade.to_json()).unwrap();
+        assert_eq!(json["nodes"][0]["input"], input);
+        assert_eq!(json["nodes"][0]["issuer"], "system");
+        assert_eq!(json["nodes"][0]["parent"], serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,411 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+pub use serde_json::Value;
+
+#[derive(Debug)]
+pub enum DeclarativeError {
+    Io(std::io::Error),
+    Parse(String),
+    UnknownComponent { rule: String, component: String },
+    NotSpatial { rule: String, component: String },
+    /// An action could not be checked against a rule, e.g. because a value isn't numeric.
+    Check { rule: String, error: String },
+}
+
+impl fmt::Display for DeclarativeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeclarativeError::Io(error) => write!(f, "could not read rules: {}", error),
+            DeclarativeError::Parse(error) => write!(f, "could not parse rules: {}", error),
+            DeclarativeError::UnknownComponent { rule, component } => {
+                write!(f, "rule `{}` uses unknown component `{}`", rule, component)
+            }
+            DeclarativeError::NotSpatial { rule, component } => {
+                write!(f, "rule `{}` needs `{}` to be a spatial component", rule, component)
+            }
+            DeclarativeError::Check { rule, error } => write!(f, "could not check rule `{}`: {}", rule, error),
+        }
+    }
+}
+
+impl std::error::Error for DeclarativeError {}
+
+impl From<std::io::Error> for DeclarativeError {
+    fn from(error: std::io::Error) -> Self {
+        DeclarativeError::Io(error)
+    }
+}
+
+/// Rules written as data. In TOML:
+///
+/// ```toml
+/// [[rule]]
+/// name = "stay on the map"
+/// component = "position"
+/// check = { InBounds = { min = [0, 0], max = [31, 31] } }
+///
+/// [[rule]]
+/// name = "enough action points"
+/// component = "action_points"
+/// check = { AtLeast = { min = 0 } }
+/// ```
+///
+/// and in RON: `(rule: [(name: "stay on the map", component: "position", check: InBounds(min: [0, 0], max: [31, 31]))])`.
+///
+/// Errors raised while the world checks an action against a rule reject the action, and
+/// are kept until `take_errors` is called. They are shared between clones, so a clone
+/// kept aside sees those of rules added to a `GameWorld`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+pub struct RuleSet {
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<DeclarativeRule>,
+    #[serde(skip)]
+    errors: Errors,
+}
+
+/// Where the errors of a rule set are kept, shared by the rules added to a `GameWorld`.
+#[doc(hidden)]
+#[derive(Debug, Clone, Default)]
+pub struct Errors(Arc<Mutex<Vec<DeclarativeError>>>);
+
+impl Errors {
+    pub fn record(&self, error: DeclarativeError) {
+        self.0.lock().unwrap().push(error);
+    }
+}
+
+// Rule sets compare by their rules.
+impl PartialEq for Errors {
+    fn eq(&self, _: &Errors) -> bool {
+        true
+    }
+}
+
+impl RuleSet {
+    pub fn from_ron(source: &str) -> Result<Self, DeclarativeError> {
+        ron::from_str(source).map_err(|error| DeclarativeError::Parse(error.to_string()))
+    }
+
+    pub fn from_toml(source: &str) -> Result<Self, DeclarativeError> {
+        toml::from_str(source).map_err(|error| DeclarativeError::Parse(error.to_string()))
+    }
+
+    /// Reads a `.ron` or `.toml` file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, DeclarativeError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("ron") => RuleSet::from_ron(&source),
+            Some("toml") => RuleSet::from_toml(&source),
+            _ => Err(DeclarativeError::Parse(format!("{} is neither a .ron nor a .toml file", path.display()))),
+        }
+    }
+
+    pub fn new(rules: Vec<DeclarativeRule>) -> Self {
+        RuleSet { rules, errors: Errors::default() }
+    }
+
+    pub fn take_errors(&self) -> Vec<DeclarativeError> {
+        std::mem::take(&mut *self.errors.0.lock().unwrap())
+    }
+
+    #[doc(hidden)]
+    pub fn errors(&self) -> Errors {
+        self.errors.clone()
+    }
+
+    /// Checks that every rule names existing components, so typos fail at load time.
+    pub fn validate(&self, components: &[&str], spatial: &[&str]) -> Result<(), DeclarativeError> {
+        for rule in &self.rules {
+            let unknown = |component: &str| DeclarativeError::UnknownComponent {
+                rule: rule.name.clone(),
+                component: component.to_string(),
+            };
+            if !components.contains(&rule.component.as_str()) {
+                return Err(unknown(&rule.component));
+            }
+            match &rule.check {
+                Constraint::WithinDistance { position, .. } if !components.contains(&position.as_str()) => {
+                    return Err(unknown(position));
+                }
+                Constraint::Unoccupied if !spatial.contains(&rule.component.as_str()) => {
+                    return Err(DeclarativeError::NotSpatial {
+                        rule: rule.name.clone(),
+                        component: rule.component.clone(),
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A constraint checked for every entity whose `component` the action inserts.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DeclarativeRule {
+    pub name: String,
+    pub component: String,
+    pub check: Constraint,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum Constraint {
+    /// Every coordinate of the new value lies within `min..=max`.
+    InBounds { min: Vec<f64>, max: Vec<f64> },
+    /// The new value, or its `field`, is at least `min`.
+    AtLeast {
+        #[serde(default)]
+        field: Option<String>,
+        min: f64,
+    },
+    /// The new value, or its `field`, is at most `max`.
+    AtMost {
+        #[serde(default)]
+        field: Option<String>,
+        max: f64,
+    },
+    /// The new value is at most `max` away from the current one.
+    MaxStep {
+        max: f64,
+        #[serde(default)]
+        metric: Metric,
+    },
+    /// The new value, or its `field`, is the id of an entity whose `position` is at most
+    /// `max` away from this entity's `position`.
+    WithinDistance {
+        #[serde(default)]
+        field: Option<String>,
+        position: String,
+        max: f64,
+        #[serde(default)]
+        metric: Metric,
+    },
+    /// No other entity shares the new value in the component's spatial index.
+    Unoccupied,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Metric {
+    /// Moves in eight directions, as with `grid::Connectivity::Eight`.
+    #[default]
+    Chebyshev,
+    Manhattan,
+    Euclidean,
+}
+
+impl Metric {
+    pub fn distance(self, a: &[f64], b: &[f64]) -> f64 {
+        let deltas = a.iter().zip(b).map(|(a, b)| (a - b).abs());
+        match self {
+            Metric::Chebyshev => deltas.fold(0.0, f64::max),
+            Metric::Manhattan => deltas.sum(),
+            Metric::Euclidean => deltas.map(|delta| delta * delta).sum::<f64>().sqrt(),
+        }
+    }
+}
+
+/// Read access to the action being checked and to the world, with ids and values as
+/// JSON values. Implemented by `register_components!`.
+pub trait RuleContext {
+    /// The entities whose `component` the action inserts, with their new values.
+    fn updated(&self, component: &str) -> Result<Vec<(Value, Value)>, String>;
+
+    /// The value of `component` for `id` as if the action was committed.
+    fn future(&self, component: &str, id: &Value) -> Result<Option<Value>, String>;
+
+    /// The committed value of `component` for `id`.
+    fn current(&self, component: &str, id: &Value) -> Result<Option<Value>, String>;
+
+    /// The other entities whose spatial `component` intersects that of `id`, as if the
+    /// action was committed.
+    fn colliding(&self, component: &str, id: &Value) -> Result<Vec<Value>, String>;
+}
+
+impl DeclarativeRule {
+    /// Whether every entity the rule applies to satisfies it. Values the constraint can't
+    /// be checked against, such as a non-numeric field, are errors.
+    pub fn passes(&self, context: &dyn RuleContext) -> Result<bool, String> {
+        for (id, value) in context.updated(&self.component)? {
+            if !self.check.holds(&self.component, &id, &value, context)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Constraint {
+    fn holds(&self, component: &str, id: &Value, value: &Value, context: &dyn RuleContext) -> Result<bool, String> {
+        Ok(match self {
+            Constraint::InBounds { min, max } => {
+                let coordinates = coordinates(value)?;
+                if coordinates.len() != min.len() || coordinates.len() != max.len() {
+                    return Err(format!("{} has {} coordinates, bounds have {}", value, coordinates.len(), min.len()));
+                }
+                coordinates
+                    .iter()
+                    .zip(min.iter().zip(max))
+                    .all(|(coordinate, (min, max))| min <= coordinate && coordinate <= max)
+            }
+            Constraint::AtLeast { field: path, min } => number(field(value, path)?)? >= *min,
+            Constraint::AtMost { field: path, max } => number(field(value, path)?)? <= *max,
+            Constraint::MaxStep { max, metric } => match context.current(component, id)? {
+                Some(current) => metric.distance(&coordinates(&current)?, &coordinates(value)?) <= *max,
+                None => true,
+            },
+            Constraint::WithinDistance { field: path, position, max, metric } => {
+                let target = field(value, path)?;
+                match (context.future(position, id)?, context.future(position, target)?) {
+                    (Some(from), Some(to)) => metric.distance(&coordinates(&from)?, &coordinates(&to)?) <= *max,
+                    _ => false,
+                }
+            }
+            Constraint::Unoccupied => context.colliding(component, id)?.is_empty(),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub fn to_value<V: Serialize>(value: &V) -> Result<Value, String> {
+    serde_json::to_value(value).map_err(|error| error.to_string())
+}
+
+#[doc(hidden)]
+pub fn from_value<V: DeserializeOwned>(value: &Value) -> Result<V, String> {
+    V::deserialize(value).map_err(|error| format!("could not convert {}: {}", value, error))
+}
+
+fn field<'a>(value: &'a Value, path: &Option<String>) -> Result<&'a Value, String> {
+    let Some(path) = path else {
+        return Ok(value);
+    };
+    path.split('.').try_fold(value, |value, segment| {
+        let next = match segment.parse::<usize>() {
+            Ok(index) => value.get(index),
+            Err(_) => value.get(segment),
+        };
+        next.ok_or_else(|| format!("{} has no field `{}`", value, segment))
+    })
+}
+
+fn number(value: &Value) -> Result<f64, String> {
+    value.as_f64().ok_or_else(|| format!("{} is not a number", value))
+}
+
+fn coordinates(value: &Value) -> Result<Vec<f64>, String> {
+    match value {
+        Value::Array(values) => values.iter().map(number).collect(),
+        value => Ok(vec![number(value)?]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Context {
+        current: HashMap<(String, u64), Value>,
+        updates: HashMap<(String, u64), Value>,
+    }
+
+    fn key(component: &str, id: &Value) -> (String, u64) {
+        (component.to_string(), id.as_u64().unwrap())
+    }
+
+    impl RuleContext for Context {
+        fn updated(&self, component: &str) -> Result<Vec<(Value, Value)>, String> {
+            Ok(self
+                .updates
+                .iter()
+                .filter(|((name, _), _)| name == component)
+                .map(|((_, id), value)| (json!(id), value.clone()))
+                .collect())
+        }
+
+        fn future(&self, component: &str, id: &Value) -> Result<Option<Value>, String> {
+            let key = key(component, id);
+            Ok(self.updates.get(&key).or_else(|| self.current.get(&key)).cloned())
+        }
+
+        fn current(&self, component: &str, id: &Value) -> Result<Option<Value>, String> {
+            Ok(self.current.get(&key(component, id)).cloned())
+        }
+
+        fn colliding(&self, _component: &str, _id: &Value) -> Result<Vec<Value>, String> {
+            Ok(Vec::new())
+        }
+    }
+
+    const RULES: &str = r#"
+        [[rule]]
+        name = "stay on the map"
+        component = "position"
+        check = { InBounds = { min = [0, 0], max = [9, 9] } }
+
+        [[rule]]
+        name = "walk one tile"
+        component = "position"
+        check = { MaxStep = { max = 1 } }
+
+        [[rule]]
+        name = "attack range"
+        component = "target"
+        check = { WithinDistance = { position = "position", max = 2, metric = "Manhattan" } }
+
+        [[rule]]
+        name = "enough action points"
+        component = "stats"
+        check = { AtLeast = { field = "action_points", min = 0 } }
+    "#;
+
+    fn rule<'a>(rules: &'a RuleSet, name: &str) -> &'a DeclarativeRule {
+        rules.rules.iter().find(|rule| rule.name == name).unwrap()
+    }
+
+    #[test]
+    fn constraints_check_updated_entities() {
+        let rules = RuleSet::from_toml(RULES).unwrap();
+        let mut context = Context::default();
+        context.current.insert(("position".to_string(), 1), json!([0, 0]));
+        context.current.insert(("position".to_string(), 2), json!([2, 1]));
+
+        context.updates.insert(("position".to_string(), 1), json!([1, 1]));
+        assert!(rule(&rules, "stay on the map").passes(&context).unwrap());
+        assert!(rule(&rules, "walk one tile").passes(&context).unwrap());
+
+        context.updates.insert(("position".to_string(), 1), json!([-1, 2]));
+        assert!(!rule(&rules, "stay on the map").passes(&context).unwrap());
+        assert!(!rule(&rules, "walk one tile").passes(&context).unwrap());
+
+        context.updates.clear();
+        context.updates.insert(("target".to_string(), 1), json!(2));
+        assert!(!rule(&rules, "attack range").passes(&context).unwrap());
+        context.updates.insert(("position".to_string(), 1), json!([1, 0]));
+        assert!(rule(&rules, "attack range").passes(&context).unwrap());
+
+        context.updates.insert(("stats".to_string(), 1), json!({ "action_points": -1 }));
+        assert!(!rule(&rules, "enough action points").passes(&context).unwrap());
+    }
+
+    #[test]
+    fn ron_and_toml_agree() {
+        let ron = RuleSet::from_ron(
+            r#"(rule: [(name: "stay on the map", component: "position", check: InBounds(min: [0, 0], max: [9, 9]))])"#,
+        )
+        .unwrap();
+
+        assert_eq!(ron.rules[0], *rule(&RuleSet::from_toml(RULES).unwrap(), "stay on the map"));
+        assert!(ron.validate(&["health"], &[]).is_err());
+        assert!(ron.validate(&["position"], &["position"]).is_ok());
+    }
+}
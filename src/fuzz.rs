@@ -0,0 +1,320 @@
+use std::fmt::{self, Debug};
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::simulation::Simulation;
+
+/// A `Simulation` that can check its own consistency. Implemented by
+/// `register_components!` with the registered invariants and the spatial indexes.
+pub trait Checked: Simulation {
+    fn check(&self) -> Result<(), String>;
+}
+
+/// SplitMix64, so a seed replays the same sequences on every platform and version.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, or 0 when `bound` is 0.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        if bound == 0 {
+            0
+        } else {
+            self.next_u64() % bound
+        }
+    }
+
+    pub fn range(&mut self, range: Range<i64>) -> i64 {
+        let span = range.end.wrapping_sub(range.start) as u64;
+        range.start.wrapping_add(self.below(span) as i64)
+    }
+
+    /// `true` with the given probability.
+    pub fn chance(&mut self, probability: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    pub fn pick<'a, V>(&mut self, values: &'a [V]) -> &'a V {
+        &values[self.below(values.len() as u64) as usize]
+    }
+}
+
+/// A sequence of inputs that breaks the simulation, shrunk as far as possible.
+#[derive(Debug, Clone)]
+pub struct Failure<T> {
+    /// The seed of the run that found it.
+    pub seed: u64,
+    /// The length of the sequence before shrinking.
+    pub generated: usize,
+    pub actions: Vec<T>,
+    /// The check that failed, or the panic raised, after the last action.
+    pub message: String,
+}
+
+impl<T: Debug> fmt::Display for Failure<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "seed {} failed after {} actions, shrunk from {}: {}",
+            self.seed,
+            self.actions.len(),
+            self.generated,
+            self.message
+        )?;
+        for (step, action) in self.actions.iter().enumerate() {
+            writeln!(f, "  {}: {:?}", step, action)?;
+        }
+        Ok(())
+    }
+}
+
+type GenerateFn<T> = Box<dyn FnMut(&mut Rng) -> T>;
+type ShrinkFn<T> = Box<dyn Fn(&T) -> Vec<T>>;
+
+/// Feeds random sequences of inputs to fresh simulations, one input per
+/// `process_actions`, and checks them after every step. Failing sequences are shrunk by
+/// dropping inputs and, given `shrink_with`, by simplifying the remaining ones.
+pub struct Fuzzer<W: Simulation> {
+    world: Box<dyn Fn() -> W>,
+    generate: GenerateFn<W::Input>,
+    shrink: Option<ShrinkFn<W::Input>>,
+    seed: u64,
+    runs: usize,
+    steps: usize,
+    max_replays: usize,
+}
+
+impl<W> Fuzzer<W>
+where
+    W: Checked,
+    W::Input: Clone + Debug,
+{
+    /// `world` builds the simulation each run starts from, `generate` picks the next input.
+    pub fn new(world: impl Fn() -> W + 'static, generate: impl FnMut(&mut Rng) -> W::Input + 'static) -> Self {
+        Fuzzer {
+            world: Box::new(world),
+            generate: Box::new(generate),
+            shrink: None,
+            seed: 0,
+            runs: 100,
+            steps: 50,
+            max_replays: 2_000,
+        }
+    }
+
+    /// Simpler variants of an input, tried in order while shrinking.
+    pub fn shrink_with(mut self, shrink: impl Fn(&W::Input) -> Vec<W::Input> + 'static) -> Self {
+        self.shrink = Some(Box::new(shrink));
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn runs(mut self, runs: usize) -> Self {
+        self.runs = runs;
+        self
+    }
+
+    /// The number of inputs of each sequence.
+    pub fn steps(mut self, steps: usize) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// The number of replays shrinking may take.
+    pub fn max_replays(mut self, replays: usize) -> Self {
+        self.max_replays = replays;
+        self
+    }
+
+    pub fn run(&mut self) -> Result<(), Failure<W::Input>> {
+        for run in 0..self.runs {
+            let seed = self.seed.wrapping_add(run as u64);
+            let mut rng = Rng::new(seed);
+            let mut world = (self.world)();
+            let mut actions = Vec::new();
+            let mut failure = check(&world).err();
+            while failure.is_none() && actions.len() < self.steps {
+                let action = (self.generate)(&mut rng);
+                actions.push(action.clone());
+                failure = step(&mut world, action).err();
+            }
+            if let Some(message) = failure {
+                let generated = actions.len();
+                let (actions, message) = self.shrink(actions, message);
+                return Err(Failure { seed, generated, actions, message });
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs and panics with the shrunk sequence if it fails.
+    #[track_caller]
+    pub fn assert(&mut self) {
+        if let Err(failure) = self.run() {
+            panic!("{}", failure);
+        }
+    }
+
+    fn shrink(&self, mut actions: Vec<W::Input>, mut message: String) -> (Vec<W::Input>, String) {
+        let mut replays = 0;
+        let mut attempt = |candidate: &[W::Input]| {
+            replays += 1;
+            (replays <= self.max_replays).then(|| self.replay(candidate)).flatten()
+        };
+
+        let mut chunk = actions.len().div_ceil(2);
+        while chunk > 0 {
+            let mut start = 0;
+            let mut removed = false;
+            while start < actions.len() {
+                let mut candidate = actions.clone();
+                candidate.drain(start..(start + chunk).min(actions.len()));
+                match attempt(&candidate) {
+                    Some((steps, failure)) => {
+                        candidate.truncate(steps);
+                        actions = candidate;
+                        message = failure;
+                        removed = true;
+                    }
+                    None => start += chunk,
+                }
+            }
+            if !removed {
+                chunk /= 2;
+            }
+        }
+
+        if let Some(shrink) = &self.shrink {
+            let mut index = 0;
+            while index < actions.len() {
+                let simpler = shrink(&actions[index]).into_iter().find_map(|variant| {
+                    let mut candidate = actions.clone();
+                    candidate[index] = variant;
+                    attempt(&candidate).map(|(steps, failure)| (candidate, steps, failure))
+                });
+                match simpler {
+                    // Try to simplify the same input further.
+                    Some((mut candidate, steps, failure)) => {
+                        candidate.truncate(steps);
+                        actions = candidate;
+                        message = failure;
+                    }
+                    None => index += 1,
+                }
+            }
+        }
+        (actions, message)
+    }
+
+    /// Returns how many inputs it took to fail, and why.
+    fn replay(&self, actions: &[W::Input]) -> Option<(usize, String)> {
+        let mut world = (self.world)();
+        if let Err(message) = check(&world) {
+            return Some((0, message));
+        }
+        for (index, action) in actions.iter().enumerate() {
+            if let Err(message) = step(&mut world, action.clone()) {
+                return Some((index + 1, message));
+            }
+        }
+        None
+    }
+}
+
+fn check<W: Checked>(world: &W) -> Result<(), String> {
+    caught(|| world.check())
+}
+
+fn step<W: Checked>(world: &mut W, action: W::Input) -> Result<(), String> {
+    caught(|| {
+        world.enqueue_action(action);
+        world.process_actions();
+        world.check()
+    })
+}
+
+/// Turns a panic, such as a broken invariant in a debug build, into a failure.
+fn caught(f: impl FnOnce() -> Result<(), String>) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(payload
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| payload.downcast_ref::<&str>().map(|message| message.to_string()))
+            .unwrap_or_else(|| "panicked".to_string()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actor::Issuer;
+
+    // Breaks once the total goes over 100.
+    #[derive(Default)]
+    struct Total {
+        total: i64,
+        pending: Vec<i64>,
+    }
+
+    impl Simulation for Total {
+        type Input = i64;
+
+        fn enqueue_action(&mut self, input: i64) {
+            self.pending.push(input);
+        }
+
+        fn enqueue_action_as(&mut self, _: Issuer, input: i64) {
+            self.enqueue_action(input);
+        }
+
+        fn process_actions(&mut self) {
+            self.total += self.pending.drain(..).sum::<i64>();
+        }
+
+        fn state_hash(&self) -> u64 {
+            self.total as u64
+        }
+    }
+
+    impl Checked for Total {
+        fn check(&self) -> Result<(), String> {
+            if self.total > 100 {
+                Err(format!("total is {}", self.total))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn failing_sequences_shrink_to_a_minimal_reproduction() {
+        let mut fuzzer = Fuzzer::new(Total::default, |rng| rng.range(-10..60))
+            .seed(7)
+            .steps(30)
+            .shrink_with(|&amount| if amount > 1 { vec![amount / 2, amount - 1] } else { Vec::new() });
+
+        let failure = fuzzer.run().unwrap_err();
+        assert_eq!(failure.actions.iter().sum::<i64>(), 101);
+        assert!(failure.actions.len() <= 3, "{}", failure);
+        assert!(failure.actions.iter().all(|&amount| amount > 0));
+
+        let failure = Fuzzer::new(Total::default, |rng| rng.range(0..2)).run();
+        assert!(failure.is_ok());
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Four,
+    Eight,
+}
+
+pub trait GridCell: Copy + Eq + Hash {
+    fn neighbors(&self, connectivity: Connectivity) -> Vec<Self>;
+
+    /// Number of steps between two cells, ignoring anything in the way.
+    fn distance(&self, other: &Self, connectivity: Connectivity) -> u32;
+}
+
+/// A cell on a square grid with integer coordinates.
+pub trait SquareCell: GridCell {
+    fn xy(&self) -> [i64; 2];
+
+    /// The cell at `xy`, or `None` when it's out of the range of the cell type.
+    fn from_xy(xy: [i64; 2]) -> Option<Self>;
+}
+
+/// The cell at `xy`, or `None` when it's out of the range of the cell type.
+pub(crate) fn cell_at<C: SquareCell>(xy: [i128; 2]) -> Option<C> {
+    C::from_xy([i64::try_from(xy[0]).ok()?, i64::try_from(xy[1]).ok()?])
+}
+
+macro_rules! impl_grid_cell {
+    ($( $scalar:ty ),*) => {
+        $(
+            impl GridCell for [$scalar; 2] {
+                fn neighbors(&self, connectivity: Connectivity) -> Vec<Self> {
+                    let [x, y] = *self;
+                    let (left, right, down, up) = (x.checked_sub(1), x.checked_add(1), y.checked_sub(1), y.checked_add(1));
+                    let mut neighbors = vec![[right, Some(y)], [left, Some(y)], [Some(x), up], [Some(x), down]];
+                    if connectivity == Connectivity::Eight {
+                        neighbors.extend([[right, up], [right, down], [left, up], [left, down]]);
+                    }
+                    // Cells on the edge of the coordinate range have fewer neighbors.
+                    neighbors.into_iter().filter_map(|[x, y]| Some([x?, y?])).collect()
+                }
+
+                fn distance(&self, other: &Self, connectivity: Connectivity) -> u32 {
+                    let dx = u32::try_from(self[0].abs_diff(other[0])).unwrap_or(u32::MAX);
+                    let dy = u32::try_from(self[1].abs_diff(other[1])).unwrap_or(u32::MAX);
+                    match connectivity {
+                        Connectivity::Four => dx.saturating_add(dy),
+                        Connectivity::Eight => dx.max(dy),
+                    }
+                }
+            }
+
+            impl SquareCell for [$scalar; 2] {
+                fn xy(&self) -> [i64; 2] {
+                    [self[0] as i64, self[1] as i64]
+                }
+
+                fn from_xy(xy: [i64; 2]) -> Option<Self> {
+                    Some([<$scalar>::try_from(xy[0]).ok()?, <$scalar>::try_from(xy[1]).ok()?])
+                }
+            }
+        )*
+    };
+}
+
+impl_grid_cell!(i8, i16, i32, i64, isize);
+
+pub trait GridObject {
+    type Cell: Eq + Hash;
+    type Id;
+
+    fn cell(&self) -> Self::Cell;
+    fn id(&self) -> &Self::Id;
+}
+
+#[derive(Debug)]
+pub struct HashGrid<T: GridObject> {
+    cells: HashMap<T::Cell, Vec<T>>,
+    size: usize,
+}
+
+impl<T: GridObject> Default for HashGrid<T> {
+    fn default() -> Self {
+        HashGrid::new()
+    }
+}
+
+impl<T: GridObject> HashGrid<T> {
+    pub fn new() -> Self {
+        HashGrid {
+            cells: HashMap::new(),
+            size: 0,
+        }
+    }
+
+    pub fn bulk_load(objects: Vec<T>) -> Self {
+        let mut grid = HashGrid::new();
+        for object in objects {
+            grid.insert(object);
+        }
+        grid
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn insert(&mut self, object: T) {
+        self.cells.entry(object.cell()).or_default().push(object);
+        self.size += 1;
+    }
+
+    pub fn remove(&mut self, object: &T) -> Option<T>
+    where
+        T: PartialEq,
+    {
+        let cell = object.cell();
+        let occupants = self.cells.get_mut(&cell)?;
+        let position = occupants.iter().position(|occupant| occupant == object)?;
+        let removed = occupants.swap_remove(position);
+        if occupants.is_empty() {
+            self.cells.remove(&cell);
+        }
+        self.size -= 1;
+        Some(removed)
+    }
+
+    pub fn contains(&self, object: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.cells.get(&object.cell()).is_some_and(|occupants| occupants.contains(object))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.cells.values().flatten()
+    }
+
+    pub fn occupants(&self, cell: &T::Cell) -> impl Iterator<Item = &T::Id> {
+        self.cells.get(cell).into_iter().flatten().map(GridObject::id)
+    }
+
+    pub fn is_occupied(&self, cell: &T::Cell) -> bool {
+        self.cells.contains_key(cell)
+    }
+
+    pub fn neighbors(&self, cell: &T::Cell, connectivity: Connectivity) -> Vec<T::Cell>
+    where
+        T::Cell: GridCell,
+    {
+        cell.neighbors(connectivity)
+    }
+
+    pub fn free_neighbors(&self, cell: &T::Cell, connectivity: Connectivity) -> Vec<T::Cell>
+    where
+        T::Cell: GridCell,
+    {
+        cell.neighbors(connectivity)
+            .into_iter()
+            .filter(|neighbor| !self.is_occupied(neighbor))
+            .collect()
+    }
+
+    pub fn occupied_neighbors(&self, cell: &T::Cell, connectivity: Connectivity) -> Vec<(T::Cell, &T::Id)>
+    where
+        T::Cell: GridCell,
+    {
+        cell.neighbors(connectivity)
+            .into_iter()
+            .flat_map(|neighbor| self.occupants(&neighbor).map(move |id| (neighbor, id)))
+            .collect()
+    }
+}
+
+// Range queries walk the cells in range when there are fewer of them than occupied
+// cells, and scan the occupied cells otherwise.
+impl<T: GridObject> HashGrid<T>
+where
+    T::Cell: SquareCell,
+{
+    /// The objects in the cells from `min` to `max`, both included.
+    pub fn in_rect(&self, min: &T::Cell, max: &T::Cell) -> Vec<&T> {
+        let (min, max) = (wide(min.xy()), wide(max.xy()));
+        self.in_box(min, max, |_| true)
+    }
+
+    /// The objects in the cells at most `radius` away from `center`.
+    pub fn within(&self, center: &T::Cell, radius: i64) -> Vec<&T> {
+        let center = wide(center.xy());
+        let radius = i128::from(radius);
+        let min = [center[0] - radius, center[1] - radius];
+        let max = [center[0] + radius, center[1] + radius];
+        self.in_box(min, max, |xy| distance_2(xy, center) <= radius * radius)
+    }
+
+    /// The `n` objects nearest to `center`, nearest first, searching rings of cells
+    /// further and further away until no unsearched cell can hold a nearer one.
+    pub fn nearest(&self, center: &T::Cell, n: usize) -> Vec<&T> {
+        let center = wide(center.xy());
+        let mut found: Vec<(i128, &T)> = Vec::new();
+        let mut radius: i128 = 0;
+        while n > 0 && found.len() < self.size {
+            if ring_size(radius) > self.cells.len() as u128 {
+                // Cheaper to scan what's left than to walk the ring.
+                found.extend(
+                    self.occupied()
+                        .filter(|&(xy, _)| chebyshev(xy, center) >= radius)
+                        .flat_map(|(xy, objects)| objects.iter().map(move |object| (distance_2(xy, center), object))),
+                );
+                break;
+            }
+            for xy in ring(center, radius) {
+                if let Some(objects) = cell_at(xy).and_then(|cell| self.cells.get(&cell)) {
+                    found.extend(objects.iter().map(|object| (distance_2(xy, center), object)));
+                }
+            }
+            // Cells outside the searched rings are more than `radius` away.
+            let beyond = (radius + 1) * (radius + 1);
+            if found.iter().filter(|&&(distance, _)| distance <= beyond).count() >= n {
+                break;
+            }
+            radius += 1;
+        }
+        found.sort_by_key(|&(distance, _)| distance);
+        found.into_iter().take(n).map(|(_, object)| object).collect()
+    }
+
+    fn in_box(&self, min: [i128; 2], max: [i128; 2], keep: impl Fn([i128; 2]) -> bool) -> Vec<&T> {
+        if min[0] > max[0] || min[1] > max[1] {
+            return Vec::new();
+        }
+        let inside = |xy: [i128; 2]| (0..2).all(|axis| min[axis] <= xy[axis] && xy[axis] <= max[axis]) && keep(xy);
+        let cells = (max[0] - min[0] + 1).saturating_mul(max[1] - min[1] + 1);
+        if cells > self.cells.len() as i128 {
+            return self
+                .occupied()
+                .filter(|&(xy, _)| inside(xy))
+                .flat_map(|(_, objects)| objects)
+                .collect();
+        }
+        let mut objects = Vec::new();
+        for x in min[0]..=max[0] {
+            for y in min[1]..=max[1] {
+                if let Some(occupants) = cell_at::<T::Cell>([x, y]).filter(|_| keep([x, y])).and_then(|cell| self.cells.get(&cell)) {
+                    objects.extend(occupants);
+                }
+            }
+        }
+        objects
+    }
+
+    fn occupied(&self) -> impl Iterator<Item = ([i128; 2], &Vec<T>)> {
+        self.cells.iter().map(|(cell, objects)| (wide(cell.xy()), objects))
+    }
+}
+
+pub(crate) fn wide(xy: [i64; 2]) -> [i128; 2] {
+    [i128::from(xy[0]), i128::from(xy[1])]
+}
+
+fn distance_2(a: [i128; 2], b: [i128; 2]) -> i128 {
+    let (dx, dy) = (a[0] - b[0], a[1] - b[1]);
+    dx * dx + dy * dy
+}
+
+fn chebyshev(a: [i128; 2], b: [i128; 2]) -> i128 {
+    (a[0] - b[0]).abs().max((a[1] - b[1]).abs())
+}
+
+fn ring_size(radius: i128) -> u128 {
+    if radius == 0 {
+        1
+    } else {
+        8 * radius as u128
+    }
+}
+
+/// The cells exactly `radius` steps away from `center` when moving in eight directions.
+fn ring(center: [i128; 2], radius: i128) -> impl Iterator<Item = [i128; 2]> {
+    let [x, y] = center;
+    let rows = (-radius..=radius).flat_map(move |dx| {
+        let bottom = [x + dx, y - radius];
+        let top = (radius > 0).then_some([x + dx, y + radius]);
+        std::iter::once(bottom).chain(top)
+    });
+    let columns = (1 - radius..radius).flat_map(move |dy| [[x - radius, y + dy], [x + radius, y + dy]]);
+    rows.chain(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Unit {
+        tile: [i32; 2],
+        id: u32,
+    }
+
+    impl GridObject for Unit {
+        type Cell = [i32; 2];
+        type Id = u32;
+
+        fn cell(&self) -> [i32; 2] {
+            self.tile
+        }
+
+        fn id(&self) -> &u32 {
+            &self.id
+        }
+    }
+
+    #[test]
+    fn tracks_occupancy() {
+        let mut grid = HashGrid::new();
+        grid.insert(Unit { tile: [3, 4], id: 1 });
+        grid.insert(Unit { tile: [3, 4], id: 2 });
+        grid.insert(Unit { tile: [4, 4], id: 3 });
+
+        let mut occupants: Vec<_> = grid.occupants(&[3, 4]).copied().collect();
+        occupants.sort();
+        assert_eq!(occupants, vec![1, 2]);
+        assert_eq!(grid.size(), 3);
+
+        assert_eq!(grid.remove(&Unit { tile: [3, 4], id: 1 }), Some(Unit { tile: [3, 4], id: 1 }));
+        assert_eq!(grid.remove(&Unit { tile: [3, 4], id: 1 }), None);
+        assert_eq!(grid.occupants(&[3, 4]).collect::<Vec<_>>(), vec![&2]);
+        assert_eq!(grid.size(), 2);
+    }
+
+    #[test]
+    fn finds_neighbors() {
+        let mut grid = HashGrid::new();
+        grid.insert(Unit { tile: [1, 0], id: 1 });
+
+        assert_eq!(grid.neighbors(&[0, 0], Connectivity::Four).len(), 4);
+        assert_eq!(grid.neighbors(&[0, 0], Connectivity::Eight).len(), 8);
+        assert!(!grid.free_neighbors(&[0, 0], Connectivity::Four).contains(&[1, 0]));
+        assert_eq!(grid.occupied_neighbors(&[0, 0], Connectivity::Eight), vec![([1, 0], &1)]);
+        assert_eq!([0, 0].distance(&[2, -3], Connectivity::Four), 5);
+        assert_eq!([0, 0].distance(&[2, -3], Connectivity::Eight), 3);
+        assert_eq!([i8::MAX, 0].neighbors(Connectivity::Eight).len(), 5);
+        assert_eq!([i8::MIN, i8::MIN].neighbors(Connectivity::Four), vec![[i8::MIN + 1, i8::MIN], [i8::MIN, i8::MIN + 1]]);
+    }
+
+    fn ids(units: Vec<&Unit>) -> Vec<u32> {
+        let mut ids: Vec<u32> = units.into_iter().map(|unit| unit.id).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn range_queries_walk_or_scan_cells() {
+        let mut grid = HashGrid::new();
+        for (id, tile) in [[0, 0], [2, 1], [5, 5], [-3, 0], [100, 100]].into_iter().enumerate() {
+            grid.insert(Unit { tile, id: id as u32 });
+        }
+
+        assert_eq!(ids(grid.in_rect(&[0, 0], &[2, 2])), vec![0, 1]);
+        assert_eq!(ids(grid.in_rect(&[-1000, -1000], &[1000, 1000])), vec![0, 1, 2, 3, 4]);
+        assert_eq!(ids(grid.in_rect(&[3, 3], &[0, 0])), Vec::<u32>::new());
+        assert_eq!(ids(grid.within(&[0, 0], 3)), vec![0, 1, 3]);
+        assert_eq!(ids(grid.within(&[0, 0], 1000)), vec![0, 1, 2, 3, 4]);
+        assert_eq!(ids(grid.within(&[0, 0], -1)), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn nearest_searches_rings_outward() {
+        let mut grid = HashGrid::new();
+        for (id, tile) in [[0, 0], [2, 1], [5, 5], [-3, 0], [100, 100]].into_iter().enumerate() {
+            grid.insert(Unit { tile, id: id as u32 });
+        }
+
+        let nearest = |center: [i32; 2], n: usize| grid.nearest(&center, n).into_iter().map(|unit| unit.id).collect::<Vec<_>>();
+        assert_eq!(nearest([0, 0], 2), vec![0, 1]);
+        assert_eq!(nearest([1, 1], 3), vec![1, 0, 3]);
+        assert_eq!(nearest([90, 90], 1), vec![4]);
+        assert_eq!(nearest([0, 0], 9), vec![0, 1, 3, 2, 4]);
+        assert_eq!(nearest([0, 0], 0), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn range_queries_stay_within_the_coordinate_range() {
+        #[derive(Debug, PartialEq)]
+        struct Small([i8; 2]);
+
+        impl GridObject for Small {
+            type Cell = [i8; 2];
+            type Id = [i8; 2];
+
+            fn cell(&self) -> [i8; 2] {
+                self.0
+            }
+
+            fn id(&self) -> &[i8; 2] {
+                &self.0
+            }
+        }
+
+        let mut grid = HashGrid::new();
+        grid.insert(Small([i8::MAX, i8::MAX]));
+        grid.insert(Small([i8::MIN, i8::MIN]));
+
+        assert_eq!(grid.within(&[i8::MAX, i8::MAX], 2), vec![&Small([i8::MAX, i8::MAX])]);
+        assert_eq!(grid.nearest(&[i8::MAX, i8::MAX], 2), vec![&Small([i8::MAX, i8::MAX]), &Small([i8::MIN, i8::MIN])]);
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::io::{self, BufRead, Write};
+
+use crate::simulation::Simulation;
+
+/// What the inspector needs from a `GameWorld`. Implemented by `register_components!`
+/// when the action and event types are `Debug`; values are shown through `Debug`.
+pub trait Inspect: Simulation {
+    fn snapshot(&self) -> Snapshot;
+
+    /// The actions waiting to be processed, with their issuer.
+    fn pending_actions(&self) -> Vec<String>;
+
+    fn events(&self) -> Vec<String>;
+
+    /// Processes the first pending action only. Returns `false` when nothing was pending.
+    fn process_next_action(&mut self) -> bool;
+
+    fn spatial_components(&self) -> Vec<&'static str>;
+
+    /// Where each entity of a spatial component is, by id, taking the center of extents.
+    fn spatial_points(&self, component: &str) -> Vec<(String, Vec<f64>)>;
+
+    /// Runs a spatial query on the index of `component`. `query` is one of `within`
+    /// (a point and a radius), `nearest` (a point and a count), `at` (a point) and `rect`
+    /// (two corners), each coordinate being a separate argument.
+    fn spatial_query(&self, component: &str, query: &str, args: &[&str]) -> Result<Vec<String>, String>;
+}
+
+/// Every component value of a state, by component name and then by entity id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub components: BTreeMap<&'static str, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Added { component: &'static str, id: String, value: String },
+    Removed { component: &'static str, id: String, value: String },
+    Updated { component: &'static str, id: String, from: String, to: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added { component, id, value } => write!(f, "+ {} {}: {}", component, id, value),
+            Change::Removed { component, id, value } => write!(f, "- {} {}: {}", component, id, value),
+            Change::Updated { component, id, from, to } => write!(f, "~ {} {}: {} -> {}", component, id, from, to),
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn insert(&mut self, component: &'static str, values: impl IntoIterator<Item = (String, String)>) {
+        self.components.entry(component).or_default().extend(values);
+    }
+
+    /// Ids of the entities with at least one component.
+    pub fn entities(&self) -> Vec<&str> {
+        let mut entities: Vec<&str> = self.components.values().flat_map(|values| values.keys()).map(String::as_str).collect();
+        entities.sort_unstable();
+        entities.dedup();
+        entities
+    }
+
+    /// The components of the entity shown as `id`.
+    pub fn entity(&self, id: &str) -> Vec<(&'static str, &str)> {
+        self.components
+            .iter()
+            .filter_map(|(&component, values)| values.get(id).map(|value| (component, value.as_str())))
+            .collect()
+    }
+
+    /// What changed from `self` to `later`.
+    pub fn diff(&self, later: &Snapshot) -> Vec<Change> {
+        let empty = BTreeMap::new();
+        let mut changes = Vec::new();
+        for (&component, after) in &later.components {
+            let before = self.components.get(component).unwrap_or(&empty);
+            for (id, value) in after {
+                match before.get(id) {
+                    None => changes.push(Change::Added { component, id: id.clone(), value: value.clone() }),
+                    Some(old) if old != value => changes.push(Change::Updated {
+                        component,
+                        id: id.clone(),
+                        from: old.clone(),
+                        to: value.clone(),
+                    }),
+                    Some(_) => {}
+                }
+            }
+            for (id, value) in before {
+                if !after.contains_key(id) {
+                    changes.push(Change::Removed { component, id: id.clone(), value: value.clone() });
+                }
+            }
+        }
+        changes
+    }
+}
+
+const HELP: &str = "\
+entities                      list entities and their components
+entity <id>                   show the components of an entity
+component <name>              show every value of a component
+pending                       show the pending actions
+events                        show the events queue
+enqueue <action>              parse and enqueue an action
+step                          process the pending actions and show what changed
+next                          process the first pending action only
+query <component> within <point> <radius>
+query <component> nearest <point> <count>
+query <component> at <point>
+query <component> rect <corner> <corner>
+save                          keep the state to diff against
+diff                          show what changed since `save`
+help                          show this help
+quit                          leave the prompt";
+
+/// A command prompt over a simulation, for debugging rule interactions without
+/// recompiling. `execute` runs one command, so a game can wire it to its own console;
+/// `run` reads commands from a stream, such as stdin.
+pub struct Inspector<P> {
+    parse: P,
+    saved: Option<Snapshot>,
+}
+
+impl<P> Inspector<P> {
+    /// `parse` turns the argument of `enqueue` into an action.
+    pub fn new(parse: P) -> Self {
+        Inspector { parse, saved: None }
+    }
+
+    /// Runs one command and returns what to show. `Ok(None)` means `quit`.
+    pub fn execute<W>(&mut self, world: &mut W, line: &str) -> Result<Option<String>, String>
+    where
+        W: Inspect,
+        P: FnMut(&str) -> Result<W::Input, String>,
+    {
+        let line = line.trim();
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        let args: Vec<&str> = rest.split_whitespace().collect();
+        let mut out = String::new();
+        match command {
+            "" => {}
+            "help" => out.push_str(HELP),
+            "quit" | "exit" => return Ok(None),
+            "entities" => {
+                let snapshot = world.snapshot();
+                for id in snapshot.entities() {
+                    let components: Vec<&str> = snapshot.entity(id).into_iter().map(|(component, _)| component).collect();
+                    let _ = writeln!(out, "{}: {}", id, components.join(", "));
+                }
+            }
+            "entity" => {
+                let snapshot = world.snapshot();
+                let components = snapshot.entity(rest);
+                if components.is_empty() {
+                    return Err(format!("no entity `{}`", rest));
+                }
+                for (component, value) in components {
+                    let _ = writeln!(out, "{}: {}", component, value);
+                }
+            }
+            "component" => {
+                let snapshot = world.snapshot();
+                let values = snapshot
+                    .components
+                    .get(rest)
+                    .ok_or_else(|| format!("no component `{}`", rest))?;
+                for (id, value) in values {
+                    let _ = writeln!(out, "{}: {}", id, value);
+                }
+            }
+            "pending" => lines(&mut out, world.pending_actions()),
+            "events" => lines(&mut out, world.events()),
+            "enqueue" => {
+                let action = (self.parse)(rest)?;
+                world.enqueue_action(action);
+            }
+            "step" | "next" => {
+                let before = world.snapshot();
+                if command == "step" {
+                    world.process_actions();
+                } else {
+                    world.process_next_action();
+                }
+                changes(&mut out, before.diff(&world.snapshot()));
+            }
+            "query" => {
+                let [component, query, args @ ..] = args.as_slice() else {
+                    return Err(format!("usage: query <{}> <query> <args>", world.spatial_components().join("|")));
+                };
+                lines(&mut out, world.spatial_query(component, query, args)?);
+            }
+            "save" => self.saved = Some(world.snapshot()),
+            "diff" => {
+                let saved = self.saved.as_ref().ok_or("nothing saved yet")?;
+                changes(&mut out, saved.diff(&world.snapshot()));
+            }
+            _ => return Err(format!("unknown command `{}`, try `help`", command)),
+        }
+        Ok(Some(out.trim_end().to_string()))
+    }
+
+    /// Prompts for commands until `quit` or the end of `input`.
+    pub fn run<W>(&mut self, world: &mut W, input: impl BufRead, mut output: impl Write) -> io::Result<()>
+    where
+        W: Inspect,
+        P: FnMut(&str) -> Result<W::Input, String>,
+    {
+        write!(output, "> ")?;
+        output.flush()?;
+        for line in input.lines() {
+            match self.execute(world, &line?) {
+                Ok(None) => return Ok(()),
+                Ok(Some(text)) if text.is_empty() => {}
+                Ok(Some(text)) => writeln!(output, "{}", text)?,
+                Err(error) => writeln!(output, "error: {}", error)?,
+            }
+            write!(output, "> ")?;
+            output.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn lines(out: &mut String, lines: Vec<String>) {
+    for line in lines {
+        let _ = writeln!(out, "{}", line);
+    }
+}
+
+fn changes(out: &mut String, changes: Vec<Change>) {
+    for change in changes {
+        let _ = writeln!(out, "{}", change);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::actor::Issuer;
+
+    #[derive(Default)]
+    pub(crate) struct Counters {
+        pub(crate) values: BTreeMap<u32, i32>,
+        pending: Vec<(u32, i32)>,
+    }
+
+    impl Simulation for Counters {
+        type Input = (u32, i32);
+
+        fn enqueue_action(&mut self, input: (u32, i32)) {
+            self.pending.push(input);
+        }
+
+        fn enqueue_action_as(&mut self, _: Issuer, input: (u32, i32)) {
+            self.enqueue_action(input);
+        }
+
+        fn process_actions(&mut self) {
+            for (id, amount) in self.pending.drain(..) {
+                *self.values.entry(id).or_default() += amount;
+            }
+        }
+
+        fn state_hash(&self) -> u64 {
+            0
+        }
+    }
+
+    impl Inspect for Counters {
+        fn snapshot(&self) -> Snapshot {
+            let mut snapshot = Snapshot::default();
+            snapshot.insert("counter", self.values.iter().map(|(id, value)| (id.to_string(), value.to_string())));
+            snapshot
+        }
+
+        fn pending_actions(&self) -> Vec<String> {
+            self.pending.iter().map(|action| format!("{:?}", action)).collect()
+        }
+
+        fn events(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        fn process_next_action(&mut self) -> bool {
+            if self.pending.is_empty() {
+                return false;
+            }
+            let next = self.pending.remove(0);
+            *self.values.entry(next.0).or_default() += next.1;
+            true
+        }
+
+        fn spatial_components(&self) -> Vec<&'static str> {
+            vec!["counter"]
+        }
+
+        fn spatial_points(&self, _: &str) -> Vec<(String, Vec<f64>)> {
+            self.values.iter().map(|(&id, &value)| (id.to_string(), vec![id as f64, value as f64])).collect()
+        }
+
+        fn spatial_query(&self, component: &str, _: &str, _: &[&str]) -> Result<Vec<String>, String> {
+            Err(format!("`{}` is not spatial", component))
+        }
+    }
+
+    fn parse(input: &str) -> Result<(u32, i32), String> {
+        let (id, amount) = input.split_once(' ').ok_or("expected `<id> <amount>`")?;
+        Ok((id.parse().map_err(|_| "bad id")?, amount.parse().map_err(|_| "bad amount")?))
+    }
+
+    #[test]
+    fn the_inspector_steps_and_diffs_the_world() {
+        let mut world = Counters::default();
+        let mut inspector = Inspector::new(parse);
+
+        let input = b"enqueue 1 5\nstep\nsave\nenqueue 1 2\nenqueue 2 1\npending\nstep\ndiff\nentity 3\nquit\nentities\n";
+        let mut output = Vec::new();
+        inspector.run(&mut world, &input[..], &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("(1, 2)\n(2, 1)"));
+        assert!(output.contains("~ counter 1: 5 -> 7\n+ counter 2: 1"));
+        assert!(output.contains("error: no entity `3`"));
+        assert!(!output.contains("1: counter"));
+    }
+}
@@ -1,13 +1,94 @@
 pub extern crate paste;
-
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub extern crate serde;
+pub extern crate tracing;
+
+pub mod actor;
+pub mod cascade;
+#[cfg(feature = "declarative")]
+pub mod declarative;
+pub mod fuzz;
+pub mod grid;
+pub mod inspector;
+pub mod lockstep;
+pub mod metrics;
+pub mod pathfinding;
+#[cfg(feature = "plugins")]
+pub mod plugins;
+pub mod region;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod simulation;
+pub mod sync;
+pub mod testing;
+pub mod transport;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod visibility;
+
+/// Generates `GameState`, `Action`, `FutureState` and `GameWorld` for the given components.
+///
+/// Spatial components are indexed as points by default. A component declared as
+/// `Footprint: extent` implements `rstar::RTreeObject<Envelope = AABB<P>>` itself and
+/// is indexed by its full envelope, so intersection queries see its whole footprint.
+/// A component declared as `Tile: grid` is indexed in a `grid::HashGrid` keyed by its
+/// value instead of an R*-tree, for direct occupancy and neighbor lookups on tile maps;
+/// it must be a `grid::SquareCell`, such as `[i32; 2]`. Spatial components must be `Copy`.
+///
+/// With this crate's `scripting` feature, and `serde_support` in the crate using the
+/// macro, `GameWorld` accepts `scripting::Script`s as rules, populate function and hooks
+/// next to plain functions. Scripts see entities keyed by id, so ids must be strings,
+/// numbers or newtypes around them.
+///
+/// With this crate's `declarative` feature, and components and ids implementing
+/// `Serialize`, `GameWorld::add_declarative_rules` turns a `declarative::RuleSet` into
+/// rules.
+///
+/// With this crate's `plugins` feature, and `serde_support` in the crate using the macro,
+/// `GameWorld` accepts sandboxed WebAssembly `plugins::Plugin`s as rules and populate
+/// function.
+///
+/// `GameWorld::process_actions` reports through `tracing`: a debug-level `action` span per
+/// processed action, with the input, its issuer, whether it was accepted and how many
+/// follow-on actions rules created, and trace-level `rule` and `hook` spans inside it.
+/// The committed state and spatial index changes are trace-level events.
+/// `GameWorld::set_audit_trail` also keeps an `ActionAudit` of the rules run on each
+/// action, their verdicts and reactions, to query once processing is done, and
+/// `GameWorld::set_metrics` times each rule, populate call, hook list and spatial update
+/// in a `metrics::Metrics` report. `GameWorld::set_cascade_recording` records which
+/// rule created each follow-on action as a `cascade::Cascade`, exported to DOT or JSON.
+///
+/// `GameWorld` implements `inspector::Inspect` when the event type is `Debug`, so an
+/// `inspector::Inspector` can list its entities, step it and run spatial queries from a
+/// command prompt. With the `tui` feature, a `tui::Dashboard` shows it live in the
+/// terminal.
+///
+/// `Scenario` drives given/when/then tests of the rules, reporting the rules run and the
+/// state changes when an expectation fails.
+///
+/// `GameWorld` implements `fuzz::Checked` with its invariants and spatial indexes, so a
+/// `fuzz::Fuzzer` can check it after every step of random action sequences.
+///
+/// The index type may be any `Clone + Eq + Hash + Debug` type, such as `u32`,
+/// `(BattleId, UnitId)` or `Uuid`. Non-`Copy` ids are passed by value and cloned
+/// internally where the generated code needs to keep a copy.
 #[macro_export]
 macro_rules! register_components {
     (
         index $index_type:ty,
         components { $( $component_type:ty ),* }
-        spatial { $( $spatial_type:ty ),* }
+        spatial { $( $spatial_type:ty $( : $spatial_kind:ident )? ),* }
+        $(
+            indexes { $( $index_name:ident : $indexed_type:ty => $key_type:ty = $projection:expr ),* }
+        )?
     ) => {
         $crate::paste::paste! {
+            const _: fn() = || {
+                fn assert_index_type<I: Clone + Eq + std::hash::Hash + Debug>() {}
+                assert_index_type::<$index_type>();
+            };
+
             use std::collections::{VecDeque, HashMap, HashSet};
             use std::fmt::Debug;
             use rstar::{AABB, RTree, RTreeObject, Envelope, PointDistance};
@@ -24,6 +105,8 @@ macro_rules! register_components {
                 $(
                     pub [<$spatial_type:lower>]: HashMap<$index_type, $spatial_type>,
                 )*
+                #[cfg_attr(feature = "serde_support", serde(skip))]
+                indexes: SecondaryIndexes,
             }
 
             impl GameState {
@@ -35,6 +118,7 @@ macro_rules! register_components {
                         $(
                             [<$spatial_type:lower>]: HashMap::new(),
                         )*
+                        indexes: SecondaryIndexes::new(),
                     }
                 }
 
@@ -45,6 +129,37 @@ macro_rules! register_components {
                     $(
                         self.[<$spatial_type:lower>].clear();
                     )*
+                    self.indexes.clear();
+                }
+
+                $($(
+                    pub fn $index_name(&self, key: &$key_type) -> impl Iterator<Item = &$index_type> + '_ {
+                        self.indexes.$index_name.get(key).into_iter().flatten()
+                    }
+                )*)?
+
+                pub fn state_hash(&self) -> u64 {
+                    let mut hash = 0u64;
+                    $(
+                        for (id, value) in &self.[<$component_type:lower>] {
+                            hash = hash.wrapping_add($crate::simulation::hash_entry(stringify!([<$component_type:lower>]), id, value));
+                        }
+                    )*
+                    $(
+                        for (id, value) in &self.[<$spatial_type:lower>] {
+                            hash = hash.wrapping_add($crate::simulation::hash_entry(stringify!([<$spatial_type:lower>]), id, value));
+                        }
+                    )*
+                    hash
+                }
+
+                pub fn rebuild_indexes(&mut self) {
+                    self.indexes.clear();
+                    $($(
+                        for (id, value) in &self.[<$indexed_type:lower>] {
+                            self.indexes.$index_name.entry(($projection)(value)).or_default().insert(id.clone());
+                        }
+                    )*)?
                 }
 
                 $(
@@ -59,7 +174,37 @@ macro_rules! register_components {
                     }
                 )*
 
+                fn update_secondary_indexes(&mut self, action: &Action) {
+                    $($(
+                        for (id, value) in &action.updates.[<$indexed_type:lower>] {
+                            if let Some(old_value) = self.[<$indexed_type:lower>].get(id) {
+                                let old_key: $key_type = ($projection)(old_value);
+                                if let Some(ids) = self.indexes.$index_name.get_mut(&old_key) {
+                                    ids.remove(id);
+                                    if ids.is_empty() {
+                                        self.indexes.$index_name.remove(&old_key);
+                                    }
+                                }
+                            }
+                            self.indexes.$index_name.entry(($projection)(value)).or_default().insert(id.clone());
+                        }
+                        for id in &action.removals.[<$indexed_type:lower>] {
+                            let current_value = action.updates.[<$indexed_type:lower>].get(id).or_else(|| self.[<$indexed_type:lower>].get(id));
+                            if let Some(current_value) = current_value {
+                                let current_key: $key_type = ($projection)(current_value);
+                                if let Some(ids) = self.indexes.$index_name.get_mut(&current_key) {
+                                    ids.remove(id);
+                                    if ids.is_empty() {
+                                        self.indexes.$index_name.remove(&current_key);
+                                    }
+                                }
+                            }
+                        }
+                    )*)?
+                }
+
                 pub fn commit_action(&mut self, action: &mut Action) {
+                    self.update_secondary_indexes(action);
                     $(
                         for (id, value) in action.updates.[<$component_type:lower>].drain() {
                             self.[<$component_type:lower>].insert(id, value);
@@ -78,6 +223,42 @@ macro_rules! register_components {
                     )*
                 }
 
+                pub fn commit_action_reversible(&mut self, action: &mut Action) -> Action {
+                    self.update_secondary_indexes(action);
+                    let mut undo = Action::new();
+                    $(
+                        for (id, value) in action.updates.[<$component_type:lower>].drain() {
+                            match self.[<$component_type:lower>].insert(id.clone(), value) {
+                                Some(old_value) => { undo.updates.[<$component_type:lower>].insert(id, old_value); }
+                                None => { undo.removals.[<$component_type:lower>].insert(id); }
+                            }
+                        }
+                        for id in action.removals.[<$component_type:lower>].drain() {
+                            if let Some(old_value) = self.[<$component_type:lower>].remove(&id) {
+                                if !undo.removals.[<$component_type:lower>].contains(&id) {
+                                    undo.updates.[<$component_type:lower>].entry(id).or_insert(old_value);
+                                }
+                            }
+                        }
+                    )*
+                    $(
+                        for (id, value) in action.updates.[<$spatial_type:lower>].drain() {
+                            match self.[<$spatial_type:lower>].insert(id.clone(), value) {
+                                Some(old_value) => { undo.updates.[<$spatial_type:lower>].insert(id, old_value); }
+                                None => { undo.removals.[<$spatial_type:lower>].insert(id); }
+                            }
+                        }
+                        for id in action.removals.[<$spatial_type:lower>].drain() {
+                            if let Some(old_value) = self.[<$spatial_type:lower>].remove(&id) {
+                                if !undo.removals.[<$spatial_type:lower>].contains(&id) {
+                                    undo.updates.[<$spatial_type:lower>].entry(id).or_insert(old_value);
+                                }
+                            }
+                        }
+                    )*
+                    undo
+                }
+
                 pub fn into_action(mut self) -> Action {
                     let mut action = Action::new();
                     $(
@@ -94,7 +275,65 @@ macro_rules! register_components {
                 }
             }
 
-            #[derive(Debug)]
+            // Cloning is only available when every component is `Clone`. The higher-ranked
+            // bounds keep these impls from failing to compile when one of them isn't.
+            impl Clone for GameState
+            where
+                $( for<'a> $component_type: Clone, )*
+                $( for<'a> $spatial_type: Clone, )*
+                for<'a> SecondaryIndexes: Clone,
+            {
+                fn clone(&self) -> Self {
+                    GameState {
+                        $(
+                            [<$component_type:lower>]: self.[<$component_type:lower>].clone(),
+                        )*
+                        $(
+                            [<$spatial_type:lower>]: self.[<$spatial_type:lower>].clone(),
+                        )*
+                        indexes: self.indexes.clone(),
+                    }
+                }
+            }
+
+            #[derive(Debug, Default)]
+            struct SecondaryIndexes {
+                $($(
+                    $index_name: HashMap<$key_type, HashSet<$index_type>>,
+                )*)?
+            }
+
+            impl Clone for SecondaryIndexes
+            where
+                $($( for<'a> $key_type: Clone, )*)?
+            {
+                fn clone(&self) -> Self {
+                    SecondaryIndexes {
+                        $($(
+                            $index_name: self.$index_name.clone(),
+                        )*)?
+                    }
+                }
+            }
+
+            impl SecondaryIndexes {
+                fn new() -> Self {
+                    SecondaryIndexes {
+                        $($(
+                            $index_name: HashMap::new(),
+                        )*)?
+                    }
+                }
+
+                fn clear(&mut self) {
+                    $($(
+                        self.$index_name.clear();
+                    )*)?
+                }
+            }
+
+            #[derive(Debug, Clone)]
+            #[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
             struct RemovedComponents {
                 $(
                     [<$component_type:lower>]: HashSet<$index_type>,
@@ -127,9 +366,25 @@ macro_rules! register_components {
             }
 
             #[derive(Debug)]
+            #[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
             pub struct Action {
                 updates: GameState,
                 removals: RemovedComponents,
+                #[cfg_attr(feature = "serde_support", serde(skip))]
+                issuer: $crate::actor::Issuer,
+            }
+
+            impl Clone for Action
+            where
+                for<'a> GameState: Clone,
+            {
+                fn clone(&self) -> Self {
+                    Action {
+                        updates: self.updates.clone(),
+                        removals: self.removals.clone(),
+                        issuer: self.issuer,
+                    }
+                }
             }
 
             impl Action {
@@ -137,9 +392,15 @@ macro_rules! register_components {
                     Action {
                         updates: GameState::new(),
                         removals: RemovedComponents::new(),
+                        issuer: $crate::actor::Issuer::System,
                     }
                 }
 
+                /// Who enqueued the action being processed, or the action that caused it.
+                pub fn issuer(&self) -> $crate::actor::Issuer {
+                    self.issuer
+                }
+
                 pub fn clear(&mut self) {
                     self.updates.clear();
                     self.removals.clear();
@@ -180,12 +441,12 @@ macro_rules! register_components {
                     }
                 )*
 
-                pub fn remove_all(&mut self, id: EntityId) {
+                pub fn remove_all(&mut self, id: $index_type) {
                     $(
-                        self.[<remove_ $component_type:lower>](id);
+                        self.[<remove_ $component_type:lower>](id.clone());
                     )*
                     $(
-                        self.[<remove_ $spatial_type:lower>](id);
+                        self.[<remove_ $spatial_type:lower>](id.clone());
                     )*
                 }
             }
@@ -198,45 +459,196 @@ macro_rules! register_components {
             impl<'a> FutureState<'a> {
                 $(
                     pub fn [<get_ $component_type:lower>](&self, id: $index_type) -> Option<&$component_type> {
-                        if let Some(value) = self.action.updates.[<get_ $component_type:lower>](id) {
-                            return Some(value);
-                        }
+                        // Removals are committed after updates, so they win.
                         if self.action.removals.[<$component_type:lower>].contains(&id) {
                             return None;
                         }
+                        if let Some(value) = self.action.updates.[<$component_type:lower>].get(&id) {
+                            return Some(value);
+                        }
                         self.state.[<get_ $component_type:lower>](id)
                     }
                 )*
                 $(
                     pub fn [<get_ $spatial_type:lower>](&self, id: $index_type) -> Option<&$spatial_type> {
-                        if let Some(value) = self.action.updates.[<get_ $spatial_type:lower>](id) {
-                            return Some(value);
-                        }
+                        // Removals are committed after updates, so they win.
                         if self.action.removals.[<$spatial_type:lower>].contains(&id) {
                             return None;
                         }
+                        if let Some(value) = self.action.updates.[<$spatial_type:lower>].get(&id) {
+                            return Some(value);
+                        }
                         self.state.[<get_ $spatial_type:lower>](id)
                     }
                 )*
+                $(
+                    fn [<pending_ $spatial_type:lower>](
+                        &self,
+                        committed: impl IntoIterator<Item = $index_type>,
+                        matches: impl Fn(&[<$spatial_type TreeObject>]) -> bool,
+                    ) -> Vec<$index_type> {
+                        let updates = &self.action.updates.[<$spatial_type:lower>];
+                        let removals = &self.action.removals.[<$spatial_type:lower>];
+                        let mut ids: Vec<$index_type> = committed
+                            .into_iter()
+                            .filter(|id| !updates.contains_key(id) && !removals.contains(id))
+                            .collect();
+                        for (id, &value) in updates.iter().filter(|(id, _)| !removals.contains(id)) {
+                            let tree_object = [<$spatial_type TreeObject>] {
+                                index: value,
+                                entity_at: id.clone(),
+                            };
+                            if matches(&tree_object) {
+                                ids.push(tree_object.entity_at);
+                            }
+                        }
+                        ids
+                    }
+
+                    pub fn [<entities_within_ $spatial_type:lower>](
+                        &self,
+                        tree: &[<$spatial_type Index>],
+                        center: &[<$spatial_type Point>],
+                        radius: <[<$spatial_type Point>] as rstar::Point>::Scalar,
+                    ) -> Vec<$index_type> {
+                        self.[<pending_ $spatial_type:lower>](
+                            tree.entities_within(center, radius),
+                            |tree_object| tree_object.distance_2(center) <= radius * radius,
+                        )
+                    }
+
+                    pub fn [<nearest_n_ $spatial_type:lower>](
+                        &self,
+                        tree: &[<$spatial_type Index>],
+                        point: &[<$spatial_type Point>],
+                        n: usize,
+                    ) -> Vec<$index_type> {
+                        let updates = &self.action.updates.[<$spatial_type:lower>];
+                        let removals = &self.action.removals.[<$spatial_type:lower>];
+                        let mut candidates = Vec::new();
+                        let committed = tree
+                            .nearest_n(point, n + updates.len() + removals.len())
+                            .into_iter()
+                            .filter(|id| !updates.contains_key(id) && !removals.contains(id))
+                            .take(n)
+                            .filter_map(|id| Some((*self.state.[<$spatial_type:lower>].get(&id)?, id)));
+                        let updated = updates
+                            .iter()
+                            .filter(|(id, _)| !removals.contains(id))
+                            .map(|(id, &value)| (value, id.clone()));
+                        for (value, id) in committed.chain(updated) {
+                            let tree_object = [<$spatial_type TreeObject>] {
+                                index: value,
+                                entity_at: id,
+                            };
+                            candidates.push((tree_object.distance_2(point), tree_object.entity_at));
+                        }
+                        candidates.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+                        candidates.into_iter().take(n).map(|(_, id)| id).collect()
+                    }
+
+                    pub fn [<entities_in_rect_ $spatial_type:lower>](
+                        &self,
+                        tree: &[<$spatial_type Index>],
+                        rect: &AABB<[<$spatial_type Point>]>,
+                    ) -> Vec<$index_type> {
+                        self.[<pending_ $spatial_type:lower>](
+                            tree.entities_in_rect(rect),
+                            |tree_object| rect.contains_envelope(&tree_object.envelope()),
+                        )
+                    }
+
+                    pub fn [<entities_intersecting_ $spatial_type:lower>](
+                        &self,
+                        tree: &[<$spatial_type Index>],
+                        rect: &AABB<[<$spatial_type Point>]>,
+                    ) -> Vec<$index_type> {
+                        self.[<pending_ $spatial_type:lower>](
+                            tree.entities_intersecting(rect),
+                            |tree_object| rect.intersects(&tree_object.envelope()),
+                        )
+                    }
+
+                    pub fn [<entity_at_ $spatial_type:lower>](
+                        &self,
+                        tree: &[<$spatial_type Index>],
+                        point: &[<$spatial_type Point>],
+                    ) -> Option<$index_type> {
+                        self.[<entities_at_ $spatial_type:lower>](tree, point).into_iter().next()
+                    }
+
+                    pub fn [<entities_at_ $spatial_type:lower>](
+                        &self,
+                        tree: &[<$spatial_type Index>],
+                        point: &[<$spatial_type Point>],
+                    ) -> Vec<$index_type> {
+                        self.[<pending_ $spatial_type:lower>](
+                            tree.entities_at(point),
+                            |tree_object| tree_object.contains_point(point),
+                        )
+                    }
+                )*
+                $($(
+                    pub fn $index_name(&self, key: &$key_type) -> Vec<$index_type> {
+                        let updates = &self.action.updates.[<$indexed_type:lower>];
+                        let removals = &self.action.removals.[<$indexed_type:lower>];
+                        let mut ids: Vec<$index_type> = self.state.$index_name(key)
+                            .filter(|&id| !updates.contains_key(id) && !removals.contains(id))
+                            .cloned()
+                            .collect();
+                        for (id, value) in updates.iter().filter(|(id, _)| !removals.contains(id)) {
+                            let updated_key: $key_type = ($projection)(value);
+                            if &updated_key == key {
+                                ids.push(id.clone());
+                            }
+                        }
+                        ids
+                    }
+                )*)?
             }
 
-            #[derive(Debug, PartialEq, Eq)]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
             pub enum ActionStatus {
                 Accept,
                 Reject,
             }
 
-            #[derive(Debug, PartialEq, Eq)]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
             pub enum RuleStatus {
                 KeepChecking,
                 StopChecking,
             }
 
+            /// One rule run on an action, as recorded in the audit trail.
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            pub struct RuleEvaluation {
+                /// Position of the rule, in the order rules were added.
+                pub rule: usize,
+                pub action_status: ActionStatus,
+                pub rule_status: RuleStatus,
+                /// Whether this rule stopped the evaluation before every rule ran.
+                pub cut_short: bool,
+                /// The follow-on actions the rule created, formatted with `Debug`.
+                pub reactions: Vec<String>,
+            }
+
+            /// The rules run on one processed action, in order.
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            pub struct ActionAudit {
+                /// The action input, formatted with `Debug`.
+                pub input: String,
+                pub issuer: $crate::actor::Issuer,
+                pub accepted: bool,
+                /// Why a permission denied the action, in which case no rule ran.
+                pub denied: Option<String>,
+                pub rules: Vec<RuleEvaluation>,
+            }
+
             pub type RuleFn<T> = fn(
                 &Action,
                 &GameState,
                 $(
-                    &RTree<[<$spatial_type TreeObject>]>,
+                    &[<$spatial_type Index>],
                 )*
             ) -> (ActionStatus, RuleStatus, Vec<T>);
 
@@ -245,7 +657,7 @@ macro_rules! register_components {
                 &GameState,
                 &mut Action,
                 $(
-                    &RTree<[<$spatial_type TreeObject>]>,
+                    &[<$spatial_type Index>],
                 )*
             );
 
@@ -254,7 +666,7 @@ macro_rules! register_components {
                 &Action,
                 &GameState,
                 $(
-                    &RTree<[<$spatial_type TreeObject>]>,
+                    &[<$spatial_type Index>],
                 )*
             );
 
@@ -262,25 +674,143 @@ macro_rules! register_components {
                 &mut VecDeque<E>,
                 &GameState,
                 $(
-                    &RTree<[<$spatial_type TreeObject>]>,
+                    &[<$spatial_type Index>],
+                )*
+            );
+
+            pub type BoxedRuleFn<T> = Box<dyn Fn(
+                &Action,
+                &GameState,
+                $(
+                    &[<$spatial_type Index>],
+                )*
+            ) -> (ActionStatus, RuleStatus, Vec<T>) + Send + Sync>;
+
+            pub type BoxedActionCreationFn<T> = Box<dyn Fn(
+                T,
+                &GameState,
+                &mut Action,
+                $(
+                    &[<$spatial_type Index>],
+                )*
+            ) + Send + Sync>;
+
+            pub type BoxedHookFn<E> = Box<dyn Fn(
+                &mut VecDeque<E>,
+                &Action,
+                &GameState,
+                $(
+                    &[<$spatial_type Index>],
                 )*
+            ) + Send + Sync>;
+
+            // Rules, populate functions and hooks are either plain function pointers or boxed
+            // closures, such as scripts. Keeping pointers unboxed spares `T` and `E` a
+            // `'static` bound.
+            enum RuleCallback<T> {
+                Pointer(RuleFn<T>),
+                Boxed(BoxedRuleFn<T>),
+            }
+
+            impl<T> RuleCallback<T> {
+                fn call(
+                    &self,
+                    action: &Action,
+                    state: &GameState,
+                    $(
+                        [<spatial_ $spatial_type:lower>]: &[<$spatial_type Index>],
+                    )*
+                ) -> (ActionStatus, RuleStatus, Vec<T>) {
+                    match self {
+                        RuleCallback::Pointer(rule) => rule(action, state, $([<spatial_ $spatial_type:lower>],)*),
+                        RuleCallback::Boxed(rule) => rule(action, state, $([<spatial_ $spatial_type:lower>],)*),
+                    }
+                }
+            }
+
+            enum ActionCreationCallback<T> {
+                Pointer(ActionCreationFn<T>),
+                Boxed(BoxedActionCreationFn<T>),
+            }
+
+            impl<T> ActionCreationCallback<T> {
+                fn call(
+                    &self,
+                    input: T,
+                    state: &GameState,
+                    action: &mut Action,
+                    $(
+                        [<spatial_ $spatial_type:lower>]: &[<$spatial_type Index>],
+                    )*
+                ) {
+                    match self {
+                        ActionCreationCallback::Pointer(populate) => populate(input, state, action, $([<spatial_ $spatial_type:lower>],)*),
+                        ActionCreationCallback::Boxed(populate) => populate(input, state, action, $([<spatial_ $spatial_type:lower>],)*),
+                    }
+                }
+            }
+
+            enum HookCallback<E> {
+                Pointer(HookFn<E>),
+                Boxed(BoxedHookFn<E>),
+            }
+
+            impl<E> HookCallback<E> {
+                fn call(
+                    &self,
+                    events: &mut VecDeque<E>,
+                    action: &Action,
+                    state: &GameState,
+                    $(
+                        [<spatial_ $spatial_type:lower>]: &[<$spatial_type Index>],
+                    )*
+                ) {
+                    match self {
+                        HookCallback::Pointer(hook) => hook(events, action, state, $([<spatial_ $spatial_type:lower>],)*),
+                        HookCallback::Boxed(hook) => hook(events, action, state, $([<spatial_ $spatial_type:lower>],)*),
+                    }
+                }
+            }
+
+            pub type InvariantFn = fn(&GameState) -> Result<(), String>;
+
+            pub type PermissionFn<T> = fn(&$crate::actor::Issuer, &T, &GameState) -> Result<(), String>;
+
+            /// Called with the position of the region, as returned when it was added.
+            pub type RegionHookFn<E> = fn(
+                &mut VecDeque<E>,
+                usize,
+                $crate::region::RegionTransition,
+                &$index_type,
+                &GameState,
             );
 
             pub struct GameWorld<T, E> {
-                populate_action: ActionCreationFn<T>,
+                populate_action: ActionCreationCallback<T>,
                 pub action: Action,
                 pub state: GameState,
-                rules: Vec<RuleFn<T>>,
-                pending_actions: VecDeque<T>,
-                follow_on_current: VecDeque<T>,
-                follow_on_accepted: VecDeque<T>,
-                follow_on_rejected: VecDeque<T>,
-                hooks_on_accepted: Vec<HookFn<E>>,
-                hooks_on_rejected: Vec<HookFn<E>>,
+                rules: Vec<RuleCallback<T>>,
+                /// Queued inputs with their issuer, what caused them and whether their permissions
+                /// are still to be checked. Follow-ons inherit the issuer but are not checked.
+                pending_actions: VecDeque<($crate::actor::Issuer, T, Option<$crate::cascade::Cause>, bool)>,
+                follow_on_current: VecDeque<($crate::actor::Issuer, T, Option<$crate::cascade::Cause>, bool)>,
+                follow_on_accepted: VecDeque<($crate::actor::Issuer, T, Option<$crate::cascade::Cause>, bool)>,
+                follow_on_rejected: VecDeque<($crate::actor::Issuer, T, Option<$crate::cascade::Cause>, bool)>,
+                hooks_on_accepted: Vec<HookCallback<E>>,
+                hooks_on_rejected: Vec<HookCallback<E>>,
                 hooks_after_commit: Vec<HookWithouActionFn<E>>,
+                invariants: Vec<(&'static str, InvariantFn)>,
+                permissions: Vec<PermissionFn<T>>,
+                audit_trail: Option<Vec<ActionAudit>>,
+                metrics: Option<$crate::metrics::Metrics>,
+                cascade: Option<$crate::cascade::Cascade>,
+                next_cascade_node: u64,
+                $(
+                    [<regions_ $spatial_type:lower>]: Vec<(Box<dyn $crate::region::Area<[<$spatial_type Point>]> + Send + Sync>, RegionHookFn<E>)>,
+                )*
                 pub events_queue: VecDeque<E>,
                 $(
-                    pub [<spatial_ $spatial_type:lower>]: RTree<[<$spatial_type TreeObject>]>,
+                    pub [<spatial_ $spatial_type:lower>]: [<$spatial_type Index>],
                 )*
             }
 
@@ -306,19 +836,28 @@ macro_rules! register_components {
                     let action = state.into_action();
                     let mut world = GameWorld {
                         action,
-                        populate_action,
+                        populate_action: ActionCreationCallback::Pointer(populate_action),
                         state: GameState::new(),
-                        rules,
+                        rules: rules.into_iter().map(RuleCallback::Pointer).collect(),
                         pending_actions: VecDeque::new(),
                         follow_on_current: VecDeque::new(),
                         follow_on_accepted: VecDeque::new(),
                         follow_on_rejected: VecDeque::new(),
-                        hooks_on_accepted,
-                        hooks_on_rejected,
+                        hooks_on_accepted: hooks_on_accepted.into_iter().map(HookCallback::Pointer).collect(),
+                        hooks_on_rejected: hooks_on_rejected.into_iter().map(HookCallback::Pointer).collect(),
                         hooks_after_commit,
+                        invariants: Vec::new(),
+                        permissions: Vec::new(),
+                        audit_trail: None,
+                        metrics: None,
+                        cascade: None,
+                        next_cascade_node: 0,
+                        $(
+                            [<regions_ $spatial_type:lower>]: Vec::new(),
+                        )*
                         events_queue: VecDeque::new(),
                         $(
-                            [<spatial_ $spatial_type:lower>]: RTree::new(),
+                            [<spatial_ $spatial_type:lower>]: [<$spatial_type Index>]::new(),
                         )*
                     };
                     world.process_initial_state();
@@ -327,177 +866,1424 @@ macro_rules! register_components {
 
                 fn process_initial_state(&mut self) {
                     for hook in &self.hooks_on_accepted {
-                        hook(&mut self.events_queue, &self.action, &self.state, $(&self.[<spatial_ $spatial_type:lower>],)*);
+                        hook.call(&mut self.events_queue, &self.action, &self.state, $(&self.[<spatial_ $spatial_type:lower>],)*);
                     }
 
+                    self.state.commit_action(&mut self.action);
+                    self.rebuild_spatial_indexes();
+                }
+
+                pub fn rebuild_spatial_indexes(&mut self) {
                     $(
-                        for (&id, &[<$spatial_type:lower>]) in &self.action.updates.[<$spatial_type:lower>] {
-                            if let Some(&[<old_ $spatial_type:lower>]) = self.state.[<get_ $spatial_type:lower>](id) {
-                                let [<old_ $spatial_type:lower _tree_object>] = [<$spatial_type TreeObject>] {
-                                    index: [<old_ $spatial_type:lower>],
-                                    entity_at: id,
-                                };
+                        let [<$spatial_type:lower _tree_objects>] = self.state.[<$spatial_type:lower>]
+                            .iter()
+                            .map(|(id, &[<$spatial_type:lower>])| [<$spatial_type TreeObject>] {
+                                index: [<$spatial_type:lower>],
+                                entity_at: id.clone(),
+                            })
+                            .collect();
+                        self.[<spatial_ $spatial_type:lower>] = [<$spatial_type Index>]::bulk_load([<$spatial_type:lower _tree_objects>]);
 
-                                self.[<spatial_ $spatial_type:lower>].remove(&[<old_ $spatial_type:lower _tree_object>]);
-                            }
+                        $crate::tracing::trace!(index = stringify!([<$spatial_type:lower>]), tree = ?self.[<spatial_ $spatial_type:lower>], "spatial index rebuilt");
+                    )*
+                }
 
-                            let [<new_ $spatial_type:lower _tree_object>] = [<$spatial_type TreeObject>] {
+                pub fn verify_spatial_indexes(&self) -> Result<(), String> {
+                    $(
+                        let index = &self.[<spatial_ $spatial_type:lower>];
+                        let components = &self.state.[<$spatial_type:lower>];
+                        if index.size() != components.len() {
+                            return Err(format!(
+                                "spatial_{} holds {} entries but GameState has {}",
+                                stringify!([<$spatial_type:lower>]), index.size(), components.len()
+                            ));
+                        }
+                        for (id, &[<$spatial_type:lower>]) in components {
+                            let tree_object = [<$spatial_type TreeObject>] {
                                 index: [<$spatial_type:lower>],
-                                entity_at: id,
+                                entity_at: id.clone(),
                             };
-
-                            self.[<spatial_ $spatial_type:lower>].insert([<new_ $spatial_type:lower _tree_object>]);
-
-                            #[cfg(feature = "debug_rtrees")]
-                            { println!("{:#?}", self.[<spatial_ $spatial_type:lower>]); }
+                            if !index.contains(&tree_object) {
+                                return Err(format!(
+                                    "spatial_{} is missing {:?}",
+                                    stringify!([<$spatial_type:lower>]), tree_object
+                                ));
+                            }
                         }
                     )*
-
-                    self.state.commit_action(&mut self.action);
+                    Ok(())
                 }
 
                 pub fn enqueue_action(&mut self, action: T) {
-                    self.pending_actions.push_back(action);
+                    self.pending_actions.push_back(($crate::actor::Issuer::System, action, None, false));
                 }
 
-                pub fn process_actions(&mut self) {
-                    while let Some(action_type) = self.pending_actions.pop_front() {
-                        #[cfg(feature = "debug_actions")]
-                        { println!("Found an action: {:#?}", action_type); }
-                        (self.populate_action)(action_type, &self.state, &mut self.action, $(&self.[<spatial_ $spatial_type:lower>],)*);
-                        #[cfg(feature = "debug_state")]
-                        { println!("GameState before commiting is: {:#?}", self.state); }
-
-                        let mut accepted = true;
-
-                        for rule in &self.rules {
-                            let (action_status, rule_status, mut reactions) = rule(
-                                &self.action,
-                                &self.state,
-                                $(
-                                    &self.[<spatial_ $spatial_type:lower>],
-                                )*
-                            );
-
-                            for a in reactions.drain(..) {
-                                self.follow_on_current.push_back(a);
-                            }
+                /// Enqueues an action on behalf of `issuer`. The permissions are checked when it is
+                /// processed, against the state left by the actions before it, and it is rejected
+                /// without running any rules if one denies it. The system is always allowed.
+                pub fn enqueue_action_as(&mut self, issuer: $crate::actor::Issuer, action: T) {
+                    let check = issuer != $crate::actor::Issuer::System;
+                    self.pending_actions.push_back((issuer, action, None, check));
+                }
 
-                            if action_status == ActionStatus::Reject {
-                                accepted = false;
+                pub fn add_permission(&mut self, permission: PermissionFn<T>) {
+                    self.permissions.push(permission);
+                }
 
-                                for a in self.follow_on_current.drain(..) {
-                                    self.follow_on_rejected.push_back(a);
-                                }
-                            } else {
-                                for a in self.follow_on_current.drain(..) {
-                                    self.follow_on_accepted.push_back(a);
-                                }
-                            }
+                /// Appends a rule, checked after the ones already registered.
+                pub fn add_rule(&mut self, rule: BoxedRuleFn<T>) {
+                    self.rules.push(RuleCallback::Boxed(rule));
+                }
 
-                            if rule_status == RuleStatus::StopChecking {
-                                break;
-                            }
-                        }
+                pub fn set_populate_action(&mut self, populate_action: BoxedActionCreationFn<T>) {
+                    self.populate_action = ActionCreationCallback::Boxed(populate_action);
+                }
 
-                        if accepted {
-                            #[cfg(feature = "debug_actions")]
-                            { println!("Action accepted"); }
-                            for hook in &self.hooks_on_accepted {
-                                hook(&mut self.events_queue, &self.action, &self.state, $(&self.[<spatial_ $spatial_type:lower>],)*);
-                            }
+                pub fn add_hook_on_accepted(&mut self, hook: BoxedHookFn<E>) {
+                    self.hooks_on_accepted.push(HookCallback::Boxed(hook));
+                }
 
-                            $(
-                                for (&id, &[<$spatial_type:lower>]) in &self.action.updates.[<$spatial_type:lower>] {
-                                    if let Some(&[<old_ $spatial_type:lower>]) = self.state.[<get_ $spatial_type:lower>](id) {
-                                        let [<old_ $spatial_type:lower _tree_object>] = [<$spatial_type TreeObject>] {
-                                            index: [<old_ $spatial_type:lower>],
-                                            entity_at: id,
-                                        };
-
-                                        self.[<spatial_ $spatial_type:lower>].remove(&[<old_ $spatial_type:lower _tree_object>]);
-                                    }
+                pub fn add_hook_on_rejected(&mut self, hook: BoxedHookFn<E>) {
+                    self.hooks_on_rejected.push(HookCallback::Boxed(hook));
+                }
 
-                                    let [<new_ $spatial_type:lower _tree_object>] = [<$spatial_type TreeObject>] {
-                                        index: [<$spatial_type:lower>],
-                                        entity_at: id,
-                                    };
+                $(
+                    pub fn [<entities_within_ $spatial_type:lower>](&self, center: &[<$spatial_type Point>], radius: <[<$spatial_type Point>] as rstar::Point>::Scalar) -> Vec<$index_type> {
+                        self.[<spatial_ $spatial_type:lower>].entities_within(center, radius)
+                    }
 
-                                    self.[<spatial_ $spatial_type:lower>].insert([<new_ $spatial_type:lower _tree_object>]);
+                    pub fn [<nearest_n_ $spatial_type:lower>](&self, point: &[<$spatial_type Point>], n: usize) -> Vec<$index_type> {
+                        self.[<spatial_ $spatial_type:lower>].nearest_n(point, n)
+                    }
 
-                                    #[cfg(feature = "debug_rtrees")]
-                                    { println!("{:#?}", self.[<spatial_ $spatial_type:lower>]); }
-                                }
+                    pub fn [<entities_in_rect_ $spatial_type:lower>](&self, rect: &AABB<[<$spatial_type Point>]>) -> Vec<$index_type> {
+                        self.[<spatial_ $spatial_type:lower>].entities_in_rect(rect)
+                    }
 
-                                for &id in &self.action.removals.[<$spatial_type:lower>] {
-                                    if let Some(&[<old_ $spatial_type:lower>]) = self.state.[<get_ $spatial_type:lower>](id) {
-                                        let [<old_ $spatial_type:lower _tree_object>] = [<$spatial_type TreeObject>] {
-                                            index: [<old_ $spatial_type:lower>],
-                                            entity_at: id,
-                                        };
+                    pub fn [<entities_intersecting_ $spatial_type:lower>](&self, rect: &AABB<[<$spatial_type Point>]>) -> Vec<$index_type> {
+                        self.[<spatial_ $spatial_type:lower>].entities_intersecting(rect)
+                    }
 
-                                        self.[<spatial_ $spatial_type:lower>].remove(&[<old_ $spatial_type:lower _tree_object>]);
-                                    }
+                    pub fn [<entity_at_ $spatial_type:lower>](&self, point: &[<$spatial_type Point>]) -> Option<$index_type> {
+                        self.[<spatial_ $spatial_type:lower>].entity_at(point)
+                    }
 
-                                    #[cfg(feature = "debug_rtrees")]
-                                    { println!("{:#?}", self.[<spatial_ $spatial_type:lower>]); }
-                                }
-                            )*
+                    pub fn [<entities_at_ $spatial_type:lower>](&self, point: &[<$spatial_type Point>]) -> Vec<$index_type> {
+                        self.[<spatial_ $spatial_type:lower>].entities_at(point)
+                    }
+                )*
 
-                            self.state.commit_action(&mut self.action);
+                $(
+                    /// Calls `on_transition` when a processed action moves an entity in or out of
+                    /// `area`. Actions committed with `apply_action` don't trigger it.
+                    pub fn [<add_ $spatial_type:lower _region>](
+                        &mut self,
+                        area: impl $crate::region::Area<[<$spatial_type Point>]> + Send + Sync + 'static,
+                        on_transition: RegionHookFn<E>,
+                    ) -> usize {
+                        self.[<regions_ $spatial_type:lower>].push((Box::new(area), on_transition));
+                        self.[<regions_ $spatial_type:lower>].len() - 1
+                    }
 
-                            for a in self.follow_on_accepted.drain(..) {
-                                self.pending_actions.push_back(a);
-                            }
-                        } else {
-                            #[cfg(feature = "debug_actions")]
-                            { println!("Action rejected"); }
-                            for hook in &self.hooks_on_rejected {
-                                hook(&mut self.events_queue, &self.action, &self.state, $(&self.[<spatial_ $spatial_type:lower>],)*);
-                            }
+                    fn [<region_transitions_ $spatial_type:lower>](&self) -> Vec<(usize, $crate::region::RegionTransition, $index_type)> {
+                        let mut transitions = Vec::new();
+                        if self.[<regions_ $spatial_type:lower>].is_empty() {
+                            return transitions;
+                        }
 
-                            self.action.clear();
+                        let updates = &self.action.updates.[<$spatial_type:lower>];
+                        let removals = &self.action.removals.[<$spatial_type:lower>];
+                        let region_point = |id: &$index_type, &value: &$spatial_type| {
+                            let tree_object = [<$spatial_type TreeObject>] {
+                                index: value,
+                                entity_at: id.clone(),
+                            };
+                            tree_object.envelope().center()
+                        };
+
+                        let changed = updates.keys().chain(removals.iter().filter(|id| !updates.contains_key(id)));
+                        for id in changed {
+                            let old_point = self.state.[<$spatial_type:lower>].get(id).map(|value| region_point(id, value));
+                            let new_point = if removals.contains(id) {
+                                None
+                            } else {
+                                updates.get(id).map(|value| region_point(id, value))
+                            };
 
-                            for a in self.follow_on_rejected.drain(..) {
-                                self.pending_actions.push_back(a);
+                            for (region, (area, _)) in self.[<regions_ $spatial_type:lower>].iter().enumerate() {
+                                let was_inside = old_point.as_ref().is_some_and(|point| area.contains(point));
+                                let is_inside = new_point.as_ref().is_some_and(|point| area.contains(point));
+                                if was_inside && !is_inside {
+                                    transitions.push((region, $crate::region::RegionTransition::Exit, id.clone()));
+                                } else if is_inside && !was_inside {
+                                    transitions.push((region, $crate::region::RegionTransition::Enter, id.clone()));
+                                }
                             }
                         }
+                        transitions
+                    }
+                )*
 
-                        for hook in &self.hooks_after_commit {
-                            hook(&mut self.events_queue, &self.state, $(&self.[<spatial_ $spatial_type:lower>],)*);
-                        }
+                pub fn add_invariant(&mut self, name: &'static str, invariant: InvariantFn) {
+                    self.invariants.push((name, invariant));
+                }
 
-                        #[cfg(feature = "debug_state")]
-                        { println!("GameState after commiting is: {:#?}", self.state); }
+                pub fn check_invariants(&self) -> Result<(), (&'static str, String)> {
+                    for &(name, invariant) in &self.invariants {
+                        invariant(&self.state).map_err(|message| (name, message))?;
                     }
+                    Ok(())
                 }
-            }
 
-            $(
-                #[derive(Debug, PartialEq)]
-                pub struct [<$spatial_type TreeObject>] {
-                    pub index: $spatial_type,
-                    pub entity_at: $index_type,
+                /// Starts or stops recording an `ActionAudit` for every processed action.
+                /// Stopping discards the audits not taken yet.
+                pub fn set_audit_trail(&mut self, enabled: bool) {
+                    self.audit_trail = if enabled { Some(self.audit_trail.take().unwrap_or_default()) } else { None };
                 }
 
-                impl RTreeObject for [<$spatial_type TreeObject>] {
-                    type Envelope = AABB<$spatial_type>;
+                /// The audits recorded since the trail was enabled or last taken.
+                pub fn audit_trail(&self) -> &[ActionAudit] {
+                    self.audit_trail.as_deref().unwrap_or_default()
+                }
 
-                    fn envelope(&self) -> Self::Envelope {
-                        AABB::from_point(self.index)
-                    }
+                pub fn take_audit_trail(&mut self) -> Vec<ActionAudit> {
+                    self.audit_trail.as_mut().map(std::mem::take).unwrap_or_default()
                 }
 
-                impl PointDistance for [<$spatial_type TreeObject>] {
-                    fn distance_2(&self, point: &<Self::Envelope as Envelope>::Point,) -> <<Self::Envelope as Envelope>::Point as Point>::Scalar {
-                        point.distance_2(&self.index)
+                /// Starts or stops timing the steps of `process_actions`. Stopping discards
+                /// the metrics collected so far.
+                pub fn set_metrics(&mut self, enabled: bool) {
+                    self.metrics = if enabled { Some(self.metrics.take().unwrap_or_default()) } else { None };
+                }
+
+                pub fn metrics(&self) -> Option<&$crate::metrics::Metrics> {
+                    self.metrics.as_ref()
+                }
+
+                pub fn take_metrics(&mut self) -> Option<$crate::metrics::Metrics> {
+                    self.metrics.as_mut().map(std::mem::take)
+                }
+
+                /// Starts or stops recording the `Cascade` of actions each `process_actions`
+                /// call processes.
+                pub fn set_cascade_recording(&mut self, enabled: bool) {
+                    self.cascade = enabled.then(|| self.cascade.take().unwrap_or_default());
+                }
+
+                /// The actions processed by the last `process_actions` call, and by
+                /// `process_next_action` calls since.
+                pub fn cascade(&self) -> Option<&$crate::cascade::Cascade> {
+                    self.cascade.as_ref()
+                }
+
+                // The state before the commit is only formatted once an invariant is broken,
+                // by undoing the commit and redoing it afterwards.
+                #[cfg(debug_assertions)]
+                fn assert_invariants(&mut self, input: Option<&str>, undo: &mut Action) {
+                    if let Err((name, message)) = self.check_invariants() {
+                        let state_after = format!("{:#?}", self.state);
+                        let mut redo = self.state.commit_action_reversible(undo);
+                        let state_before = format!("{:#?}", self.state);
+                        let action = match input {
+                            Some(input) => input.to_string(),
+                            None => format!("{:?}", redo),
+                        };
+                        self.state.commit_action(&mut redo);
+                        panic!(
+                            "Invariant `{}` broken by action {}: {}\nGameState before committing was: {}\nGameState after committing is: {}",
+                            name, action, message, state_before, state_after
+                        );
                     }
                 }
-            )*
-        }
+
+                pub fn process_actions(&mut self) {
+                    self.process_actions_with(false, |_| ());
+                }
+
+                /// Commits `action` as is, without running rules, hooks or region hooks.
+                /// Invariants are still checked in debug builds.
+                pub fn apply_action(&mut self, action: Action) {
+                    #[cfg(debug_assertions)]
+                    if !self.invariants.is_empty() {
+                        self.apply_action_reversible(action);
+                        return;
+                    }
+                    self.action = action;
+                    self.update_spatial_indexes();
+                    self.state.commit_action(&mut self.action);
+                }
+
+                pub fn apply_action_reversible(&mut self, action: Action) -> Action {
+                    self.action = action;
+                    self.update_spatial_indexes();
+                    #[allow(unused_mut)]
+                    let mut undo = self.state.commit_action_reversible(&mut self.action);
+                    #[cfg(debug_assertions)]
+                    self.assert_invariants(None, &mut undo);
+                    undo
+                }
+
+                fn update_spatial_indexes(&mut self) {
+                    $(
+                        for (id, &[<$spatial_type:lower>]) in &self.action.updates.[<$spatial_type:lower>] {
+                            if let Some(&[<old_ $spatial_type:lower>]) = self.state.[<$spatial_type:lower>].get(id) {
+                                let [<old_ $spatial_type:lower _tree_object>] = [<$spatial_type TreeObject>] {
+                                    index: [<old_ $spatial_type:lower>],
+                                    entity_at: id.clone(),
+                                };
+
+                                self.[<spatial_ $spatial_type:lower>].remove(&[<old_ $spatial_type:lower _tree_object>]);
+                            }
+
+                            let [<new_ $spatial_type:lower _tree_object>] = [<$spatial_type TreeObject>] {
+                                index: [<$spatial_type:lower>],
+                                entity_at: id.clone(),
+                            };
+
+                            self.[<spatial_ $spatial_type:lower>].insert([<new_ $spatial_type:lower _tree_object>]);
+                            $crate::tracing::trace!(index = stringify!([<$spatial_type:lower>]), id = ?id, "spatial index updated");
+                        }
+
+                        for id in &self.action.removals.[<$spatial_type:lower>] {
+                            if let Some(&[<old_ $spatial_type:lower>]) = self.state.[<$spatial_type:lower>].get(id) {
+                                let [<old_ $spatial_type:lower _tree_object>] = [<$spatial_type TreeObject>] {
+                                    index: [<old_ $spatial_type:lower>],
+                                    entity_at: id.clone(),
+                                };
+
+                                self.[<spatial_ $spatial_type:lower>].remove(&[<old_ $spatial_type:lower _tree_object>]);
+                            }
+                            $crate::tracing::trace!(index = stringify!([<$spatial_type:lower>]), id = ?id, "spatial index removed");
+                        }
+                    )*
+                }
+
+                /// Processes only the first pending action, leaving the follow-on actions it
+                /// creates in the queue. Returns `false` when nothing was pending.
+                pub fn process_next_action(&mut self) -> bool {
+                    self.process_next_with(false, &mut |_| ()).is_some()
+                }
+
+                /// Processes every pending action, passing each accepted one to `record` before
+                /// it is committed. Returns the undo of each accepted action if `reversible`.
+                fn process_actions_with(&mut self, reversible: bool, mut record: impl FnMut(&Action)) -> Vec<Action> {
+                    if let Some(cascade) = &mut self.cascade {
+                        cascade.nodes.clear();
+                    }
+                    let mut undo = Vec::new();
+                    while let Some(action_undo) = self.process_next_with(reversible, &mut record) {
+                        if reversible {
+                            undo.extend(action_undo);
+                        }
+                    }
+                    undo
+                }
+
+                /// Returns `None` when nothing was pending, and otherwise the undo of the
+                /// processed action if it was accepted and committed reversibly, which it is
+                /// when `reversible` or when invariants are checked.
+                fn process_next_with(&mut self, reversible: bool, record: &mut impl FnMut(&Action)) -> Option<Option<Action>> {
+                    let (issuer, action_type, cause, check) = self.pending_actions.pop_front()?;
+                    let cascade_node = self.cascade.as_ref().map(|_| {
+                        self.next_cascade_node += 1;
+                        $crate::cascade::CascadeNode {
+                            id: self.next_cascade_node - 1,
+                            input: format!("{:?}", action_type),
+                            issuer,
+                            accepted: true,
+                            rejected_by: None,
+                            cause,
+                        }
+                    });
+                    let action_watch = $crate::metrics::Stopwatch::start(self.metrics.is_some());
+                    let action_span = $crate::tracing::debug_span!(
+                        "action",
+                        input = ?action_type,
+                        issuer = %issuer,
+                        accepted = $crate::tracing::field::Empty,
+                        follow_ons = $crate::tracing::field::Empty,
+                    );
+                    let _action_guard = action_span.enter();
+                    let mut audit = self.audit_trail.is_some().then(|| ActionAudit {
+                        input: format!("{:?}", action_type),
+                        issuer,
+                        accepted: true,
+                        denied: None,
+                        rules: Vec::new(),
+                    });
+
+                    let denied = if check {
+                        self.permissions.iter().find_map(|permission| permission(&issuer, &action_type, &self.state).err())
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = denied {
+                        $crate::tracing::debug!(%reason, "denied");
+                        action_span.record("accepted", false);
+                        action_span.record("follow_ons", 0);
+                        if let (Some(trail), Some(mut audit)) = (&mut self.audit_trail, audit) {
+                            audit.accepted = false;
+                            audit.denied = Some(reason);
+                            trail.push(audit);
+                        }
+                        if let (Some(cascade), Some(mut node)) = (&mut self.cascade, cascade_node) {
+                            node.accepted = false;
+                            cascade.nodes.push(node);
+                        }
+                        action_watch.stop(self.metrics.as_mut().map(|metrics| &mut metrics.actions));
+                        return Some(None);
+                    }
+                    #[cfg(debug_assertions)]
+                    let action_description = if self.invariants.is_empty() {
+                        None
+                    } else {
+                        Some(format!("{:?}", action_type))
+                    };
+                    self.action.issuer = issuer;
+                    let watch = $crate::metrics::Stopwatch::start(self.metrics.is_some());
+                    self.populate_action.call(action_type, &self.state, &mut self.action, $(&self.[<spatial_ $spatial_type:lower>],)*);
+                    watch.stop(self.metrics.as_mut().map(|metrics| &mut metrics.populate));
+                    $crate::tracing::trace!(action = ?self.action, "populated");
+
+                    let mut accepted = true;
+                    let mut undo = None;
+                    let mut rejected_by = None;
+                    let mut follow_ons = 0;
+
+                    for (index, rule) in self.rules.iter().enumerate() {
+                        let rule_span = $crate::tracing::trace_span!(
+                            "rule",
+                            index,
+                            verdict = $crate::tracing::field::Empty,
+                            stop = $crate::tracing::field::Empty,
+                            follow_ons = $crate::tracing::field::Empty,
+                        );
+                        let _rule_guard = rule_span.enter();
+                        let watch = $crate::metrics::Stopwatch::start(self.metrics.is_some());
+                        let (action_status, rule_status, mut reactions) = rule.call(
+                            &self.action,
+                            &self.state,
+                            $(
+                                &self.[<spatial_ $spatial_type:lower>],
+                            )*
+                        );
+                        watch.stop(self.metrics.as_mut().map(|metrics| metrics.rule(index)));
+                        rule_span.record("verdict", $crate::tracing::field::debug(&action_status));
+                        rule_span.record("stop", rule_status == RuleStatus::StopChecking);
+                        rule_span.record("follow_ons", reactions.len());
+                        follow_ons += reactions.len();
+                        if let Some(audit) = &mut audit {
+                            audit.rules.push(RuleEvaluation {
+                                rule: index,
+                                action_status,
+                                rule_status,
+                                cut_short: rule_status == RuleStatus::StopChecking && index + 1 < self.rules.len(),
+                                reactions: reactions.iter().map(|reaction| format!("{:?}", reaction)).collect(),
+                            });
+                        }
+
+                        let cause = cascade_node.as_ref().map(|node| $crate::cascade::Cause {
+                            parent: node.id,
+                            rule: index,
+                            rule_accepted: action_status == ActionStatus::Accept,
+                        });
+                        for a in reactions.drain(..) {
+                            self.follow_on_current.push_back((issuer, a, cause, false));
+                        }
+
+                        if action_status == ActionStatus::Reject {
+                            if accepted {
+                                rejected_by = Some(index);
+                            }
+                            accepted = false;
+
+                            for a in self.follow_on_current.drain(..) {
+                                self.follow_on_rejected.push_back(a);
+                            }
+                        } else {
+                            for a in self.follow_on_current.drain(..) {
+                                self.follow_on_accepted.push_back(a);
+                            }
+                        }
+
+                        if rule_status == RuleStatus::StopChecking {
+                            break;
+                        }
+                    }
+
+                    action_span.record("accepted", accepted);
+                    action_span.record("follow_ons", follow_ons);
+                    if let (Some(trail), Some(mut audit)) = (&mut self.audit_trail, audit) {
+                        audit.accepted = accepted;
+                        trail.push(audit);
+                    }
+                    if let (Some(cascade), Some(mut node)) = (&mut self.cascade, cascade_node) {
+                        node.accepted = accepted;
+                        node.rejected_by = rejected_by;
+                        cascade.nodes.push(node);
+                    }
+
+                    if accepted {
+                        let watch = $crate::metrics::Stopwatch::start(self.metrics.is_some());
+                        for (index, hook) in self.hooks_on_accepted.iter().enumerate() {
+                            let _hook_guard = $crate::tracing::trace_span!("hook", on = "accepted", index).entered();
+                            hook.call(&mut self.events_queue, &self.action, &self.state, $(&self.[<spatial_ $spatial_type:lower>],)*);
+                        }
+                        watch.stop(self.metrics.as_mut().map(|metrics| &mut metrics.hooks_on_accepted));
+
+                        $(
+                            let [<$spatial_type:lower _region_transitions>] = self.[<region_transitions_ $spatial_type:lower>]();
+                        )*
+
+                        let watch = $crate::metrics::Stopwatch::start(self.metrics.is_some());
+                        self.update_spatial_indexes();
+                        watch.stop(self.metrics.as_mut().map(|metrics| &mut metrics.spatial_updates));
+
+                        record(&self.action);
+                        if reversible || (cfg!(debug_assertions) && !self.invariants.is_empty()) {
+                            undo = Some(self.state.commit_action_reversible(&mut self.action));
+                        } else {
+                            self.state.commit_action(&mut self.action);
+                        }
+
+                        $(
+                            for (region, transition, id) in [<$spatial_type:lower _region_transitions>] {
+                                let (_, on_transition) = &self.[<regions_ $spatial_type:lower>][region];
+                                on_transition(&mut self.events_queue, region, transition, &id, &self.state);
+                            }
+                        )*
+
+                        #[cfg(debug_assertions)]
+                        if let (Some(input), Some(undo)) = (&action_description, &mut undo) {
+                            self.assert_invariants(Some(input), undo);
+                        }
+
+                        for a in self.follow_on_accepted.drain(..) {
+                            self.pending_actions.push_back(a);
+                        }
+                    } else {
+                        let watch = $crate::metrics::Stopwatch::start(self.metrics.is_some());
+                        for (index, hook) in self.hooks_on_rejected.iter().enumerate() {
+                            let _hook_guard = $crate::tracing::trace_span!("hook", on = "rejected", index).entered();
+                            hook.call(&mut self.events_queue, &self.action, &self.state, $(&self.[<spatial_ $spatial_type:lower>],)*);
+                        }
+                        watch.stop(self.metrics.as_mut().map(|metrics| &mut metrics.hooks_on_rejected));
+
+                        self.action.clear();
+
+                        for a in self.follow_on_rejected.drain(..) {
+                            self.pending_actions.push_back(a);
+                        }
+                    }
+
+                    let watch = $crate::metrics::Stopwatch::start(self.metrics.is_some());
+                    for (index, hook) in self.hooks_after_commit.iter().enumerate() {
+                        let _hook_guard = $crate::tracing::trace_span!("hook", on = "after_commit", index).entered();
+                        hook(&mut self.events_queue, &self.state, $(&self.[<spatial_ $spatial_type:lower>],)*);
+                    }
+                    watch.stop(self.metrics.as_mut().map(|metrics| &mut metrics.hooks_after_commit));
+                    action_watch.stop(self.metrics.as_mut().map(|metrics| &mut metrics.actions));
+
+                    $crate::tracing::trace!(state = ?self.state, "processed");
+                    Some(undo)
+                }
+            }
+
+            $crate::__impl_state_view!(
+                index $index_type,
+                components { $( [<$component_type:lower>]: $component_type, )* $( [<$spatial_type:lower>]: $spatial_type, )* }
+            );
+
+            $crate::__impl_scripting!(index $index_type, spatial { $( [<$spatial_type Index>] ),* });
+
+            $crate::__impl_plugins!($( [<$spatial_type Index>] ),*);
+
+            $crate::__impl_declarative!(
+                index $index_type,
+                components { $( [<$component_type:lower>]: $component_type, )* $( [<$spatial_type:lower>]: $spatial_type, )* }
+                spatial { $( [<$spatial_type:lower>]: [<$spatial_type Index>] => [<$spatial_type TreeObject>], )* }
+            );
+
+            impl<T: Debug, E> $crate::simulation::Simulation for GameWorld<T, E> {
+                type Input = T;
+
+                fn enqueue_action(&mut self, input: T) {
+                    GameWorld::enqueue_action(self, input);
+                }
+
+                fn enqueue_action_as(&mut self, issuer: $crate::actor::Issuer, input: T) {
+                    GameWorld::enqueue_action_as(self, issuer, input);
+                }
+
+                fn process_actions(&mut self) {
+                    GameWorld::process_actions(self);
+                }
+
+                fn state_hash(&self) -> u64 {
+                    self.state.state_hash()
+                }
+            }
+
+            impl<T: Debug, E: Debug> $crate::inspector::Inspect for GameWorld<T, E> {
+                fn snapshot(&self) -> $crate::inspector::Snapshot {
+                    let mut snapshot = $crate::inspector::Snapshot::default();
+                    $(
+                        snapshot.insert(
+                            stringify!([<$component_type:lower>]),
+                            self.state.[<$component_type:lower>].iter().map(|(id, value)| (format!("{:?}", id), format!("{:?}", value))),
+                        );
+                    )*
+                    $(
+                        snapshot.insert(
+                            stringify!([<$spatial_type:lower>]),
+                            self.state.[<$spatial_type:lower>].iter().map(|(id, value)| (format!("{:?}", id), format!("{:?}", value))),
+                        );
+                    )*
+                    snapshot
+                }
+
+                fn pending_actions(&self) -> Vec<String> {
+                    self.pending_actions.iter().map(|(issuer, action, _, _)| format!("{}: {:?}", issuer, action)).collect()
+                }
+
+                fn events(&self) -> Vec<String> {
+                    self.events_queue.iter().map(|event| format!("{:?}", event)).collect()
+                }
+
+                fn process_next_action(&mut self) -> bool {
+                    GameWorld::process_next_action(self)
+                }
+
+                fn spatial_components(&self) -> Vec<&'static str> {
+                    vec![$(stringify!([<$spatial_type:lower>]),)*]
+                }
+
+                fn spatial_points(&self, component: &str) -> Vec<(String, Vec<f64>)> {
+                    fn coordinates<P: rstar::Point>(point: P) -> Vec<f64> {
+                        (0..P::DIMENSIONS)
+                            .map(|axis| format!("{:?}", point.nth(axis)).parse().unwrap_or(f64::NAN))
+                            .collect()
+                    }
+
+                    match component {
+                        $(
+                            stringify!([<$spatial_type:lower>]) => self.[<spatial_ $spatial_type:lower>]
+                                .iter()
+                                .map(|tree_object| (format!("{:?}", tree_object.entity_at), coordinates(tree_object.envelope().center())))
+                                .collect(),
+                        )*
+                        _ => Vec::new(),
+                    }
+                }
+
+                fn spatial_query(&self, component: &str, query: &str, args: &[&str]) -> Result<Vec<String>, String> {
+                    fn scalar<S: rstar::RTreeNum>(arg: &str) -> Result<S, String> {
+                        S::from_str_radix(arg, 10).map_err(|_| format!("`{}` is not a number", arg))
+                    }
+
+                    fn point<P: rstar::Point>(args: &[&str]) -> Result<P, String> {
+                        let coordinates = args[..P::DIMENSIONS].iter().map(|arg| scalar(arg)).collect::<Result<Vec<P::Scalar>, _>>()?;
+                        Ok(P::generate(|axis| coordinates[axis]))
+                    }
+
+                    fn run<Q: SpatialQuery>(index: &Q, query: &str, args: &[&str]) -> Result<Vec<$index_type>, String> {
+                        let dimensions = <Q::Point as rstar::Point>::DIMENSIONS;
+                        let expect = |count: usize| {
+                            if args.len() == count {
+                                Ok(())
+                            } else {
+                                Err(format!("`{}` takes {} arguments", query, count))
+                            }
+                        };
+                        match query {
+                            "within" => {
+                                expect(dimensions + 1)?;
+                                Ok(index.entities_within(&point(args)?, scalar(args[dimensions])?))
+                            }
+                            "nearest" => {
+                                expect(dimensions + 1)?;
+                                let count = args[dimensions].parse().map_err(|_| format!("`{}` is not a count", args[dimensions]))?;
+                                Ok(index.nearest_n(&point(args)?, count))
+                            }
+                            "at" => {
+                                expect(dimensions)?;
+                                Ok(index.entities_at(&point(args)?))
+                            }
+                            "rect" => {
+                                expect(2 * dimensions)?;
+                                Ok(index.entities_in_rect(&AABB::from_corners(point(args)?, point(&args[dimensions..])?)))
+                            }
+                            _ => Err(format!("unknown query `{}`, expected within, nearest, at or rect", query)),
+                        }
+                    }
+
+                    let ids = match component {
+                        $(
+                            stringify!([<$spatial_type:lower>]) => run(&self.[<spatial_ $spatial_type:lower>], query, args)?,
+                        )*
+                        _ => return Err(format!("`{}` is not a spatial component", component)),
+                    };
+                    Ok(ids.iter().map(|id| format!("{:?}", id)).collect())
+                }
+            }
+
+            impl<T: Debug, E> $crate::fuzz::Checked for GameWorld<T, E> {
+                fn check(&self) -> Result<(), String> {
+                    self.check_invariants()
+                        .map_err(|(name, message)| format!("invariant `{}` broken: {}", name, message))?;
+                    self.verify_spatial_indexes()
+                }
+            }
+
+            /// A given/when/then test of the rules of a `GameWorld`: `given` sets up the state
+            /// without running any rule, `when` enqueues the actions under test and `then`
+            /// processes them into an `Outcome` to check.
+            pub struct Scenario<T, E> {
+                world: GameWorld<T, E>,
+                actions: usize,
+            }
+
+            impl<T: Debug, E: Debug> Scenario<T, E> {
+                pub fn new(world: GameWorld<T, E>) -> Self {
+                    Scenario { world, actions: 0 }
+                }
+
+                /// A scenario on a world with only these rules and populate function, and no hooks.
+                pub fn with_rules(rules: Vec<RuleFn<T>>, populate_action: ActionCreationFn<T>) -> Self {
+                    Scenario::new(GameWorld::new(rules, populate_action, vec![], vec![], vec![]))
+                }
+
+                pub fn given(mut self, setup: impl FnOnce(&mut Action)) -> Self {
+                    let mut action = Action::new();
+                    setup(&mut action);
+                    self.world.apply_action(action);
+                    self
+                }
+
+                pub fn when(mut self, input: T) -> Self {
+                    self.world.enqueue_action(input);
+                    self.actions += 1;
+                    self
+                }
+
+                pub fn when_as(mut self, issuer: $crate::actor::Issuer, input: T) -> Self {
+                    self.world.enqueue_action_as(issuer, input);
+                    self.actions += 1;
+                    self
+                }
+
+                pub fn then(mut self) -> Outcome<T, E> {
+                    let before = $crate::inspector::Inspect::snapshot(&self.world);
+                    let audit_trail = self.world.audit_trail.replace(Vec::new());
+                    self.world.process_actions();
+                    let audits = std::mem::replace(&mut self.world.audit_trail, audit_trail).unwrap_or_default();
+                    Outcome {
+                        world: self.world,
+                        before,
+                        audits,
+                        actions: self.actions,
+                    }
+                }
+            }
+
+            /// The result of a `Scenario`. The `expect_` methods panic with the rules run on
+            /// each action and the changes made to the state when the expectation fails.
+            pub struct Outcome<T, E> {
+                world: GameWorld<T, E>,
+                before: $crate::inspector::Snapshot,
+                audits: Vec<ActionAudit>,
+                actions: usize,
+            }
+
+            impl<T: Debug, E: Debug> Outcome<T, E> {
+                pub fn world(&self) -> &GameWorld<T, E> {
+                    &self.world
+                }
+
+                pub fn into_world(self) -> GameWorld<T, E> {
+                    self.world
+                }
+
+                /// The actions processed, those given to `when` first, then their follow-ons.
+                pub fn audits(&self) -> &[ActionAudit] {
+                    &self.audits
+                }
+
+                #[track_caller]
+                pub fn expect_accepted(self) -> Self {
+                    let verdicts = vec![true; self.actions];
+                    self.expect_verdicts(&verdicts)
+                }
+
+                #[track_caller]
+                pub fn expect_rejected(self) -> Self {
+                    let verdicts = vec![false; self.actions];
+                    self.expect_verdicts(&verdicts)
+                }
+
+                /// Whether each action given to `when` was accepted, in order.
+                #[track_caller]
+                pub fn expect_verdicts(self, accepted: &[bool]) -> Self {
+                    let actual: Vec<bool> = self.audits.iter().take(self.actions).map(|audit| audit.accepted).collect();
+                    if actual != accepted {
+                        let verdict = |accepted: &bool| if *accepted { "accepted" } else { "rejected" };
+                        self.fail(format!(
+                            "unexpected verdicts:\n{}",
+                            $crate::testing::diff_lines(
+                                &accepted.iter().map(verdict).map(String::from).collect::<Vec<_>>(),
+                                &actual.iter().map(verdict).map(String::from).collect::<Vec<_>>(),
+                            ),
+                        ));
+                    }
+                    self
+                }
+
+                /// The actions processed as a consequence of those given to `when`, in order,
+                /// compared through `Debug`.
+                #[track_caller]
+                pub fn expect_follow_ons(self, expected: &[T]) -> Self {
+                    let expected = $crate::testing::debug_lines(expected);
+                    let actual: Vec<String> = self.audits.iter().skip(self.actions).map(|audit| audit.input.clone()).collect();
+                    if actual != expected {
+                        self.fail(format!("unexpected follow-on actions:\n{}", $crate::testing::diff_lines(&expected, &actual)));
+                    }
+                    self
+                }
+
+                #[track_caller]
+                pub fn expect_events(self, expected: &[E]) -> Self
+                where
+                    E: PartialEq,
+                {
+                    if !self.world.events_queue.iter().eq(expected.iter()) {
+                        self.fail(format!(
+                            "unexpected events:\n{}",
+                            $crate::testing::diff_lines(
+                                &$crate::testing::debug_lines(expected),
+                                &$crate::testing::debug_lines(&self.world.events_queue),
+                            ),
+                        ));
+                    }
+                    self
+                }
+
+                $(
+                    #[track_caller]
+                    pub fn [<expect_ $component_type:lower>](self, id: $index_type, expected: $component_type) -> Self
+                    where
+                        for<'a> $component_type: PartialEq,
+                    {
+                        let actual = self.world.state.[<$component_type:lower>].get(&id);
+                        if actual != Some(&expected) {
+                            self.fail(format!("unexpected {} of {:?}:\n- {:?}\n+ {:?}", stringify!([<$component_type:lower>]), id, Some(&expected), actual));
+                        }
+                        self
+                    }
+
+                    #[track_caller]
+                    pub fn [<expect_no_ $component_type:lower>](self, id: $index_type) -> Self {
+                        let actual = self.world.state.[<$component_type:lower>].get(&id);
+                        if actual.is_some() {
+                            self.fail(format!("unexpected {} of {:?}:\n- None\n+ {:?}", stringify!([<$component_type:lower>]), id, actual));
+                        }
+                        self
+                    }
+                )*
+
+                $(
+                    #[track_caller]
+                    pub fn [<expect_ $spatial_type:lower>](self, id: $index_type, expected: $spatial_type) -> Self
+                    where
+                        for<'a> $spatial_type: PartialEq,
+                    {
+                        let actual = self.world.state.[<$spatial_type:lower>].get(&id);
+                        if actual != Some(&expected) {
+                            self.fail(format!("unexpected {} of {:?}:\n- {:?}\n+ {:?}", stringify!([<$spatial_type:lower>]), id, Some(&expected), actual));
+                        }
+                        self
+                    }
+
+                    #[track_caller]
+                    pub fn [<expect_no_ $spatial_type:lower>](self, id: $index_type) -> Self {
+                        let actual = self.world.state.[<$spatial_type:lower>].get(&id);
+                        if actual.is_some() {
+                            self.fail(format!("unexpected {} of {:?}:\n- None\n+ {:?}", stringify!([<$spatial_type:lower>]), id, actual));
+                        }
+                        self
+                    }
+                )*
+
+                #[track_caller]
+                fn fail(&self, message: String) -> ! {
+                    let mut report = message;
+                    report.push_str("\n\nactions:");
+                    for audit in &self.audits {
+                        report.push_str(&format!(
+                            "\n  {} by {}: {}",
+                            audit.input,
+                            audit.issuer,
+                            if audit.accepted { "accepted" } else { "rejected" },
+                        ));
+                        if let Some(reason) = &audit.denied {
+                            report.push_str(&format!("\n    denied: {}", reason));
+                        }
+                        for rule in &audit.rules {
+                            report.push_str(&format!("\n    rule {}: {:?}, {:?}", rule.rule, rule.action_status, rule.rule_status));
+                            if rule.cut_short {
+                                report.push_str(" (cut short)");
+                            }
+                            if !rule.reactions.is_empty() {
+                                report.push_str(&format!(", reactions [{}]", rule.reactions.join(", ")));
+                            }
+                        }
+                    }
+                    report.push_str("\n\nchanges:");
+                    for change in self.before.diff(&$crate::inspector::Inspect::snapshot(&self.world)) {
+                        report.push_str(&format!("\n  {}", change));
+                    }
+                    panic!("{}", report);
+                }
+            }
+
+            impl<T: Debug, E> $crate::sync::Authoritative for GameWorld<T, E>
+            where
+                for<'a> Action: Clone,
+            {
+                type Delta = Action;
+
+                fn process_recording(&mut self) -> Vec<Action> {
+                    let mut deltas = Vec::new();
+                    self.process_actions_with(false, |action| deltas.push(action.clone()));
+                    deltas
+                }
+
+                fn process_reversible(&mut self) -> Vec<Action> {
+                    self.process_actions_with(true, |_| ())
+                }
+
+                fn apply_delta(&mut self, delta: Action) {
+                    self.apply_action(delta);
+                }
+
+                fn pending_events(&self) -> usize {
+                    self.events_queue.len()
+                }
+
+                fn discard_events(&mut self, count: usize) {
+                    self.events_queue.truncate(self.events_queue.len().saturating_sub(count));
+                }
+            }
+
+            pub trait SpatialQuery {
+                type Point: rstar::Point;
+
+                fn entities_within(&self, center: &Self::Point, radius: <Self::Point as rstar::Point>::Scalar) -> Vec<$index_type>;
+                fn nearest_n(&self, point: &Self::Point, n: usize) -> Vec<$index_type>;
+                fn entities_in_rect(&self, rect: &AABB<Self::Point>) -> Vec<$index_type>;
+                fn entities_intersecting(&self, rect: &AABB<Self::Point>) -> Vec<$index_type>;
+                fn entity_at(&self, point: &Self::Point) -> Option<$index_type>;
+                fn entities_at(&self, point: &Self::Point) -> Vec<$index_type>;
+
+                /// The entities in the cells of `fov`, each once even when it covers several of them.
+                fn entities_visible(&self, fov: &$crate::visibility::FieldOfView<Self::Point>) -> Vec<$index_type>
+                where
+                    Self::Point: $crate::grid::SquareCell,
+                {
+                    let mut seen = HashSet::new();
+                    fov.visible_with(|cell| self.entities_at(cell))
+                        .into_iter()
+                        .filter(|entity| seen.insert(entity.clone()))
+                        .collect()
+                }
+            }
+
+            $(
+                pub type [<$spatial_type Point>] = $crate::__spatial_point!($($spatial_kind)?; $spatial_type);
+
+                pub type [<$spatial_type Index>] = $crate::__spatial_index!($($spatial_kind)?; [<$spatial_type TreeObject>]);
+
+                $crate::__impl_spatial_query!(
+                    $($spatial_kind)?;
+                    [<$spatial_type Index>],
+                    [<$spatial_type Point>],
+                    [<$spatial_type TreeObject>],
+                    $index_type
+                );
+
+                $crate::__impl_grid_object!(
+                    $($spatial_kind)?;
+                    [<$spatial_type TreeObject>],
+                    [<$spatial_type Point>],
+                    $index_type
+                );
+
+                #[derive(Debug, PartialEq)]
+                pub struct [<$spatial_type TreeObject>] {
+                    pub index: $spatial_type,
+                    pub entity_at: $index_type,
+                }
+
+                impl RTreeObject for [<$spatial_type TreeObject>] {
+                    type Envelope = AABB<[<$spatial_type Point>]>;
+
+                    fn envelope(&self) -> Self::Envelope {
+                        $crate::__spatial_envelope!($($spatial_kind)?; self.index)
+                    }
+                }
+
+                impl PointDistance for [<$spatial_type TreeObject>] {
+                    fn distance_2(&self, point: &<Self::Envelope as Envelope>::Point,) -> <<Self::Envelope as Envelope>::Point as Point>::Scalar {
+                        self.envelope().distance_2(point)
+                    }
+                }
+            )*
+        }
     }
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spatial_point {
+    (; $spatial_type:ty) => { $spatial_type };
+    (extent; $spatial_type:ty) => { <<$spatial_type as rstar::RTreeObject>::Envelope as rstar::Envelope>::Point };
+    (grid; $spatial_type:ty) => { $spatial_type };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spatial_envelope {
+    (; $value:expr) => { rstar::AABB::from_point($value) };
+    (extent; $value:expr) => { rstar::RTreeObject::envelope(&$value) };
+    (grid; $value:expr) => { rstar::AABB::from_point($value) };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spatial_index {
+    (; $tree_object:ty) => { rstar::RTree<$tree_object> };
+    (extent; $tree_object:ty) => { rstar::RTree<$tree_object> };
+    (grid; $tree_object:ty) => { $crate::grid::HashGrid<$tree_object> };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_grid_object {
+    (; $($rest:tt)*) => {};
+    (extent; $($rest:tt)*) => {};
+    (grid; $tree_object:ty, $point:ty, $index_type:ty) => {
+        impl $crate::grid::GridObject for $tree_object {
+            type Cell = $point;
+            type Id = $index_type;
+
+            fn cell(&self) -> $point {
+                self.index
+            }
+
+            fn id(&self) -> &$index_type {
+                &self.entity_at
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_spatial_query {
+    (; $($rest:tt)*) => { $crate::__impl_spatial_query!(@rtree $($rest)*); };
+    (extent; $($rest:tt)*) => { $crate::__impl_spatial_query!(@rtree $($rest)*); };
+    (@rtree $spatial_index:ty, $point:ty, $tree_object:ty, $index_type:ty) => {
+        impl SpatialQuery for $spatial_index {
+            type Point = $point;
+
+            fn entities_within(&self, center: &$point, radius: <$point as rstar::Point>::Scalar) -> Vec<$index_type> {
+                if self.size() == 0 {
+                    return Vec::new();
+                }
+                self.locate_within_distance(*center, radius * radius)
+                    .map(|tree_object| tree_object.entity_at.clone())
+                    .collect()
+            }
+
+            fn nearest_n(&self, point: &$point, n: usize) -> Vec<$index_type> {
+                if self.size() == 0 {
+                    return Vec::new();
+                }
+                self.nearest_neighbor_iter(point)
+                    .take(n)
+                    .map(|tree_object| tree_object.entity_at.clone())
+                    .collect()
+            }
+
+            fn entities_in_rect(&self, rect: &rstar::AABB<$point>) -> Vec<$index_type> {
+                self.locate_in_envelope(rect)
+                    .map(|tree_object| tree_object.entity_at.clone())
+                    .collect()
+            }
+
+            fn entities_intersecting(&self, rect: &rstar::AABB<$point>) -> Vec<$index_type> {
+                self.locate_in_envelope_intersecting(rect)
+                    .map(|tree_object| tree_object.entity_at.clone())
+                    .collect()
+            }
+
+            fn entity_at(&self, point: &$point) -> Option<$index_type> {
+                self.locate_at_point(point).map(|tree_object| tree_object.entity_at.clone())
+            }
+
+            fn entities_at(&self, point: &$point) -> Vec<$index_type> {
+                self.locate_all_at_point(point)
+                    .map(|tree_object| tree_object.entity_at.clone())
+                    .collect()
+            }
+        }
+    };
+    (grid; $spatial_index:ty, $point:ty, $tree_object:ty, $index_type:ty) => {
+        impl SpatialQuery for $spatial_index {
+            type Point = $point;
+
+            fn entities_within(&self, center: &$point, radius: <$point as rstar::Point>::Scalar) -> Vec<$index_type> {
+                let radius = $crate::grid::SquareCell::xy(&<$point as rstar::Point>::generate(|_| radius))[0];
+                self.within(center, radius)
+                    .into_iter()
+                    .map(|tree_object| tree_object.entity_at.clone())
+                    .collect()
+            }
+
+            fn nearest_n(&self, point: &$point, n: usize) -> Vec<$index_type> {
+                self.nearest(point, n)
+                    .into_iter()
+                    .map(|tree_object| tree_object.entity_at.clone())
+                    .collect()
+            }
+
+            fn entities_in_rect(&self, rect: &rstar::AABB<$point>) -> Vec<$index_type> {
+                self.in_rect(&rect.lower(), &rect.upper())
+                    .into_iter()
+                    .map(|tree_object| tree_object.entity_at.clone())
+                    .collect()
+            }
+
+            fn entities_intersecting(&self, rect: &rstar::AABB<$point>) -> Vec<$index_type> {
+                self.entities_in_rect(rect)
+            }
+
+            fn entity_at(&self, point: &$point) -> Option<$index_type> {
+                self.occupants(point).next().cloned()
+            }
+
+            fn entities_at(&self, point: &$point) -> Vec<$index_type> {
+                self.occupants(point).cloned().collect()
+            }
+        }
+    };
+}
+
+
+// The scripting, plugins and declarative impls are generated by these helpers rather than
+// by `#[cfg(feature = ..)]` blocks inside `register_components!`, which would check the
+// features of the crate using the macro instead of ours. Each has an empty version for
+// when its feature is off.
+
+#[cfg(any(feature = "scripting", feature = "plugins"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_state_view {
+    (
+        index $index_type:ty,
+        components { $( $component:ident : $component_type:ty, )* }
+    ) => {
+        /// Some of the components of a `GameState`, serialized like it. Scripts and plugins
+        /// get this rather than the whole state, so a call only costs the components it reads.
+        #[doc(hidden)]
+        pub struct StateView<'a> {
+            state: &'a GameState,
+            components: Option<&'a [String]>,
+        }
+
+        impl<'a> StateView<'a> {
+            /// Every component if `components` is `None`.
+            pub fn new(state: &'a GameState, components: Option<&'a [String]>) -> Self {
+                StateView { state, components }
+            }
+        }
+
+        impl $crate::serde::Serialize for StateView<'_>
+        where
+            for<'a> $index_type: $crate::serde::Serialize,
+            $( for<'a> $component_type: $crate::serde::Serialize, )*
+        {
+            fn serialize<S: $crate::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                use $crate::serde::ser::SerializeMap;
+
+                let mut map = serializer.serialize_map(None)?;
+                $(
+                    if self.components.map_or(true, |components| components.iter().any(|component| component == stringify!($component))) {
+                        map.serialize_entry(stringify!($component), &self.state.$component)?;
+                    }
+                )*
+                map.end()
+            }
+        }
+    };
+}
+
+#[cfg(not(any(feature = "scripting", feature = "plugins")))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_state_view {
+    ($($tokens:tt)*) => {};
+}
+
+#[cfg(feature = "scripting")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_scripting {
+    (index $index_type:ty, spatial { $( $spatial_index:ty ),* }) => {
+        impl<T, E> GameWorld<T, E>
+        where
+            T: std::fmt::Debug + $crate::serde::Serialize + $crate::serde::de::DeserializeOwned + 'static,
+            E: $crate::serde::de::DeserializeOwned + 'static,
+            for<'a> $index_type: $crate::serde::de::DeserializeOwned,
+            for<'a> StateView<'a>: $crate::serde::Serialize,
+            for<'a> Action: $crate::serde::Serialize + $crate::serde::de::DeserializeOwned,
+        {
+            /// Fails if ids can't be keys of the maps scripts see, e.g. tuples.
+            pub fn add_script_rule(&mut self, script: $crate::scripting::Script) -> Result<(), $crate::scripting::ScriptError> {
+                $crate::scripting::check_id_type::<$index_type>()?;
+                self.add_rule(Box::new(move |action: &Action, state: &GameState, $(_: &$spatial_index,)*| {
+                    match script.rule::<_, _, T>(action, &StateView::new(state, script.components())) {
+                        Ok(verdict) => (
+                            if verdict.accept { ActionStatus::Accept } else { ActionStatus::Reject },
+                            if verdict.stop { RuleStatus::StopChecking } else { RuleStatus::KeepChecking },
+                            verdict.actions,
+                        ),
+                        Err(error) => {
+                            script.record_error(error);
+                            (ActionStatus::Reject, RuleStatus::KeepChecking, Vec::new())
+                        }
+                    }
+                }));
+                Ok(())
+            }
+
+            pub fn set_populate_script(&mut self, script: $crate::scripting::Script) -> Result<(), $crate::scripting::ScriptError> {
+                $crate::scripting::check_id_type::<$index_type>()?;
+                self.set_populate_action(Box::new(move |input: T, state: &GameState, action: &mut Action, $(_: &$spatial_index,)*| {
+                    match script.populate(&input, &StateView::new(state, script.components()), &*action) {
+                        Ok(populated) => {
+                            let issuer = action.issuer;
+                            *action = populated;
+                            action.issuer = issuer;
+                        }
+                        Err(error) => script.record_error(error),
+                    }
+                }));
+                Ok(())
+            }
+
+            pub fn add_script_hook_on_accepted(&mut self, script: $crate::scripting::Script) -> Result<(), $crate::scripting::ScriptError> {
+                $crate::scripting::check_id_type::<$index_type>()?;
+                self.add_hook_on_accepted(Self::script_hook(script));
+                Ok(())
+            }
+
+            pub fn add_script_hook_on_rejected(&mut self, script: $crate::scripting::Script) -> Result<(), $crate::scripting::ScriptError> {
+                $crate::scripting::check_id_type::<$index_type>()?;
+                self.add_hook_on_rejected(Self::script_hook(script));
+                Ok(())
+            }
+
+            fn script_hook(script: $crate::scripting::Script) -> BoxedHookFn<E> {
+                Box::new(move |events: &mut std::collections::VecDeque<E>, action: &Action, state: &GameState, $(_: &$spatial_index,)*| {
+                    match script.hook(action, &StateView::new(state, script.components())) {
+                        Ok(new_events) => events.extend(new_events),
+                        Err(error) => script.record_error(error),
+                    }
+                })
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "scripting"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_scripting {
+    ($($tokens:tt)*) => {};
+}
+
+#[cfg(feature = "plugins")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_plugins {
+    ($( $spatial_index:ty ),*) => {
+        impl<T, E> GameWorld<T, E>
+        where
+            T: std::fmt::Debug + $crate::serde::Serialize + $crate::serde::de::DeserializeOwned + 'static,
+            for<'a> StateView<'a>: $crate::serde::Serialize,
+            for<'a> Action: $crate::serde::Serialize + $crate::serde::de::DeserializeOwned,
+        {
+            pub fn add_plugin_rule(&mut self, plugin: $crate::plugins::Plugin) {
+                self.add_rule(Box::new(move |action: &Action, state: &GameState, $(_: &$spatial_index,)*| {
+                    match plugin.rule::<_, _, T>(action, &StateView::new(state, plugin.components())) {
+                        Ok(verdict) => (
+                            if verdict.accept { ActionStatus::Accept } else { ActionStatus::Reject },
+                            if verdict.stop { RuleStatus::StopChecking } else { RuleStatus::KeepChecking },
+                            verdict.actions,
+                        ),
+                        Err(error) => {
+                            plugin.record_error(error);
+                            (ActionStatus::Reject, RuleStatus::KeepChecking, Vec::new())
+                        }
+                    }
+                }));
+            }
+
+            pub fn set_populate_plugin(&mut self, plugin: $crate::plugins::Plugin) {
+                self.set_populate_action(Box::new(move |input: T, state: &GameState, action: &mut Action, $(_: &$spatial_index,)*| {
+                    match plugin.populate(&input, &StateView::new(state, plugin.components()), &*action) {
+                        Ok(populated) => {
+                            let issuer = action.issuer;
+                            *action = populated;
+                            action.issuer = issuer;
+                        }
+                        Err(error) => plugin.record_error(error),
+                    }
+                }));
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "plugins"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_plugins {
+    ($($tokens:tt)*) => {};
+}
+
+#[cfg(feature = "declarative")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_declarative {
+    (
+        index $index_type:ty,
+        components { $( $component:ident : $component_type:ty, )* }
+        spatial { $( $spatial:ident : $spatial_index:ty => $tree_object:ty, )* }
+    ) => {
+        $crate::paste::paste! {
+            struct DeclarativeContext<'a> {
+                future: FutureState<'a>,
+                $(
+                    [<spatial_ $spatial>]: &'a $spatial_index,
+                )*
+            }
+
+            impl $crate::declarative::RuleContext for DeclarativeContext<'_>
+            where
+                for<'a> $index_type: $crate::serde::Serialize + $crate::serde::de::DeserializeOwned,
+                $( for<'a> $component_type: $crate::serde::Serialize, )*
+            {
+                fn updated(&self, component: &str) -> Result<Vec<($crate::declarative::Value, $crate::declarative::Value)>, String> {
+                    match component {
+                        $(
+                            stringify!($component) => self.future.action.updates.$component
+                                .iter()
+                                .map(|(id, value)| Ok(($crate::declarative::to_value(id)?, $crate::declarative::to_value(value)?)))
+                                .collect(),
+                        )*
+                        _ => Err(format!("unknown component `{}`", component)),
+                    }
+                }
+
+                fn future(&self, component: &str, id: &$crate::declarative::Value) -> Result<Option<$crate::declarative::Value>, String> {
+                    let id: $index_type = $crate::declarative::from_value(id)?;
+                    match component {
+                        $(
+                            stringify!($component) => self.future.[<get_ $component>](id).map($crate::declarative::to_value).transpose(),
+                        )*
+                        _ => Err(format!("unknown component `{}`", component)),
+                    }
+                }
+
+                fn current(&self, component: &str, id: &$crate::declarative::Value) -> Result<Option<$crate::declarative::Value>, String> {
+                    let id: $index_type = $crate::declarative::from_value(id)?;
+                    match component {
+                        $(
+                            stringify!($component) => self.future.state.[<get_ $component>](id).map($crate::declarative::to_value).transpose(),
+                        )*
+                        _ => Err(format!("unknown component `{}`", component)),
+                    }
+                }
+
+                fn colliding(&self, component: &str, id: &$crate::declarative::Value) -> Result<Vec<$crate::declarative::Value>, String> {
+                    let id: $index_type = $crate::declarative::from_value(id)?;
+                    match component {
+                        $(
+                            stringify!($spatial) => {
+                                let Some(&value) = self.future.[<get_ $spatial>](id.clone()) else {
+                                    return Ok(Vec::new());
+                                };
+                                let tree_object = $tree_object {
+                                    index: value,
+                                    entity_at: id.clone(),
+                                };
+                                self.future.[<entities_intersecting_ $spatial>](self.[<spatial_ $spatial>], &rstar::RTreeObject::envelope(&tree_object))
+                                    .iter()
+                                    .filter(|&other| *other != id)
+                                    .map($crate::declarative::to_value)
+                                    .collect()
+                            }
+                        )*
+                        _ => Err(format!("`{}` is not a spatial component", component)),
+                    }
+                }
+            }
+
+            impl<T: std::fmt::Debug + 'static, E> GameWorld<T, E>
+            where
+                for<'a> DeclarativeContext<'a>: $crate::declarative::RuleContext,
+            {
+                /// Adds one rule per declarative rule, rejecting actions that break it. Actions
+                /// it can't be checked against, e.g. with a non-numeric value, are rejected too,
+                /// and the error is kept until `rules.take_errors()` is called.
+                pub fn add_declarative_rules(&mut self, rules: &$crate::declarative::RuleSet) -> Result<(), $crate::declarative::DeclarativeError> {
+                    rules.validate(
+                        &[$(stringify!($component),)*],
+                        &[$(stringify!($spatial),)*],
+                    )?;
+                    let errors = rules.errors();
+                    for rule in rules.rules.iter().cloned() {
+                        let errors = errors.clone();
+                        self.add_rule(Box::new(move |action: &Action, state: &GameState, $([<spatial_ $spatial>]: &$spatial_index,)*| {
+                            let context = DeclarativeContext {
+                                future: FutureState { state, action },
+                                $(
+                                    [<spatial_ $spatial>],
+                                )*
+                            };
+                            let passes = rule.passes(&context).unwrap_or_else(|error| {
+                                errors.record($crate::declarative::DeclarativeError::Check { rule: rule.name.clone(), error });
+                                false
+                            });
+                            let status = if passes { ActionStatus::Accept } else { ActionStatus::Reject };
+                            (status, RuleStatus::KeepChecking, Vec::new())
+                        }));
+                    }
+                    Ok(())
+                }
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "declarative"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_declarative {
+    ($($tokens:tt)*) => {};
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
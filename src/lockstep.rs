@@ -0,0 +1,406 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tcp")]
+use serde::{Deserialize, Serialize};
+
+pub use crate::actor::PlayerId;
+use crate::actor::Issuer;
+use crate::simulation::Simulation;
+use crate::transport::{PeerId, Transport, TransportError};
+
+/// How many turns past its current one a session accepts messages for. Peers can't get
+/// further ahead than this, since each turn waits for the inputs of every player.
+pub const MAX_TURNS_AHEAD: u64 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "tcp", derive(Serialize, Deserialize))]
+pub enum LockstepMessage<T> {
+    Inputs { turn: u64, inputs: Vec<T> },
+    Hash { turn: u64, hash: u64 },
+}
+
+#[derive(Debug)]
+pub enum SessionError {
+    Transport(TransportError),
+    Desync { turn: u64, player: PlayerId, local_hash: u64, remote_hash: u64 },
+    UnknownPeer(PeerId),
+    /// A player sent their inputs or their hash for a turn twice, or after it was processed.
+    Duplicate { turn: u64, player: PlayerId },
+    /// A player sent a message for a turn more than `MAX_TURNS_AHEAD` after the current one.
+    TooFarAhead { turn: u64, player: PlayerId },
+    TurnAlreadyEnded(u64),
+    Timeout,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::Transport(error) => write!(f, "{}", error),
+            SessionError::Desync { turn, player, local_hash, remote_hash } => write!(
+                f,
+                "player {} desynced on turn {}: local hash {:016x}, remote hash {:016x}",
+                player, turn, local_hash, remote_hash
+            ),
+            SessionError::UnknownPeer(peer) => write!(f, "message from unknown peer {}", peer),
+            SessionError::Duplicate { turn, player } => {
+                write!(f, "player {} sent turn {} twice", player, turn)
+            }
+            SessionError::TooFarAhead { turn, player } => {
+                write!(f, "player {} sent turn {} too far ahead", player, turn)
+            }
+            SessionError::TurnAlreadyEnded(turn) => write!(f, "turn {} was already ended", turn),
+            SessionError::Timeout => write!(f, "timed out waiting for other players"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+impl From<TransportError> for SessionError {
+    fn from(error: TransportError) -> Self {
+        SessionError::Transport(error)
+    }
+}
+
+/// Runs a `Simulation` in lockstep with other peers.
+///
+/// Each turn every player submits their inputs and calls `end_turn`. Once the inputs of
+/// all players for the turn are in, they are enqueued ordered by player id and then by
+/// submission order, processed, and the resulting state hash is shared with the other
+/// peers to detect desyncs. A turn is forgotten once the hashes of every other player
+/// have been checked against it.
+///
+/// Messages are attributed to the player of the peer they arrived from, so a peer can't
+/// send inputs on behalf of another.
+pub struct LockstepSession<S: Simulation, Tr> {
+    simulation: S,
+    transport: Tr,
+    local_player: PlayerId,
+    peers: BTreeMap<PeerId, PlayerId>,
+    players: Vec<PlayerId>,
+    turn: u64,
+    turn_ended: bool,
+    local_inputs: Vec<S::Input>,
+    inputs: BTreeMap<u64, BTreeMap<PlayerId, Vec<S::Input>>>,
+    /// Hashes of the processed turns, with the players whose hash is still to be checked.
+    local_hashes: BTreeMap<u64, (u64, BTreeSet<PlayerId>)>,
+    remote_hashes: Vec<(u64, PlayerId, u64)>,
+}
+
+impl<S, Tr> LockstepSession<S, Tr>
+where
+    S: Simulation,
+    S::Input: Clone,
+    Tr: Transport<LockstepMessage<S::Input>>,
+{
+    /// `peers` maps the other peers of `transport` to the player each one plays as.
+    pub fn new(simulation: S, transport: Tr, local_player: PlayerId, peers: impl IntoIterator<Item = (PeerId, PlayerId)>) -> Self {
+        let peers: BTreeMap<_, _> = peers.into_iter().collect();
+        let mut players: Vec<_> = peers.values().copied().chain([local_player]).collect();
+        players.sort_unstable();
+        players.dedup();
+        LockstepSession {
+            simulation,
+            transport,
+            local_player,
+            peers,
+            players,
+            turn: 0,
+            turn_ended: false,
+            local_inputs: Vec::new(),
+            inputs: BTreeMap::new(),
+            local_hashes: BTreeMap::new(),
+            remote_hashes: Vec::new(),
+        }
+    }
+
+    pub fn simulation(&self) -> &S {
+        &self.simulation
+    }
+
+    /// Mutable access to the simulation, e.g. to drain its events. Changing its state
+    /// outside of the session will desync it from the other peers.
+    pub fn simulation_mut(&mut self) -> &mut S {
+        &mut self.simulation
+    }
+
+    pub fn turn(&self) -> u64 {
+        self.turn
+    }
+
+    pub fn local_player(&self) -> PlayerId {
+        self.local_player
+    }
+
+    pub fn submit(&mut self, input: S::Input) {
+        self.local_inputs.push(input);
+    }
+
+    /// Sends the inputs submitted for the current turn, which may be none.
+    pub fn end_turn(&mut self) -> Result<(), SessionError> {
+        if self.turn_ended {
+            return Err(SessionError::TurnAlreadyEnded(self.turn));
+        }
+        let inputs = std::mem::take(&mut self.local_inputs);
+        self.transport.send(LockstepMessage::Inputs {
+            turn: self.turn,
+            inputs: inputs.clone(),
+        })?;
+        self.inputs.entry(self.turn).or_default().insert(self.local_player, inputs);
+        self.turn_ended = true;
+        Ok(())
+    }
+
+    /// Handles received messages and processes the current turn if every player's inputs
+    /// have arrived. Returns the processed turn, if any.
+    pub fn poll(&mut self) -> Result<Option<u64>, SessionError> {
+        while let Some((peer, message)) = self.transport.receive()? {
+            let player = *self.peers.get(&peer).ok_or(SessionError::UnknownPeer(peer))?;
+            let turn = match message {
+                LockstepMessage::Inputs { turn, .. } | LockstepMessage::Hash { turn, .. } => turn,
+            };
+            if turn > self.turn.saturating_add(MAX_TURNS_AHEAD) {
+                return Err(SessionError::TooFarAhead { turn, player });
+            }
+            match message {
+                LockstepMessage::Inputs { turn, inputs } => {
+                    if turn < self.turn || self.inputs.get(&turn).is_some_and(|turn_inputs| turn_inputs.contains_key(&player)) {
+                        return Err(SessionError::Duplicate { turn, player });
+                    }
+                    self.inputs.entry(turn).or_default().insert(player, inputs);
+                }
+                LockstepMessage::Hash { turn, hash } => {
+                    let stale = turn < self.turn && !self.local_hashes.contains_key(&turn);
+                    if stale || self.remote_hashes.iter().any(|&(pending, from, _)| (pending, from) == (turn, player)) {
+                        return Err(SessionError::Duplicate { turn, player });
+                    }
+                    self.remote_hashes.push((turn, player, hash));
+                }
+            }
+        }
+
+        self.check_hashes()?;
+        if self.turn_ended && self.turn_is_complete() {
+            Ok(Some(self.process_turn()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Polls until the current turn has been processed.
+    pub fn wait_for_turn(&mut self, timeout: Duration) -> Result<u64, SessionError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(turn) = self.poll()? {
+                return Ok(turn);
+            }
+            if Instant::now() >= deadline {
+                return Err(SessionError::Timeout);
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    fn turn_is_complete(&self) -> bool {
+        self.inputs
+            .get(&self.turn)
+            .is_some_and(|inputs| self.players.iter().all(|player| inputs.contains_key(player)))
+    }
+
+    fn process_turn(&mut self) -> Result<u64, SessionError> {
+        let turn = self.turn;
+        for (player, inputs) in self.inputs.remove(&turn).unwrap_or_default() {
+            for input in inputs {
+                // Every peer denies the same inputs when processing them.
+                self.simulation.enqueue_action_as(Issuer::Player(player), input);
+            }
+        }
+        self.simulation.process_actions();
+
+        let hash = self.simulation.state_hash();
+        let unchecked: BTreeSet<_> = self.players.iter().copied().filter(|&player| player != self.local_player).collect();
+        if !unchecked.is_empty() {
+            self.local_hashes.insert(turn, (hash, unchecked));
+        }
+        self.transport.send(LockstepMessage::Hash { turn, hash })?;
+
+        self.turn += 1;
+        self.turn_ended = false;
+        Ok(turn)
+    }
+
+    fn check_hashes(&mut self) -> Result<(), SessionError> {
+        let mut error = None;
+        let local_hashes = &mut self.local_hashes;
+        self.remote_hashes.retain(|&(turn, player, remote_hash)| match local_hashes.get_mut(&turn) {
+            Some((local_hash, unchecked)) => {
+                if error.is_none() {
+                    if !unchecked.remove(&player) {
+                        error = Some(SessionError::Duplicate { turn, player });
+                    } else if *local_hash != remote_hash {
+                        error = Some(SessionError::Desync { turn, player, local_hash: *local_hash, remote_hash });
+                    }
+                }
+                false
+            }
+            None => true,
+        });
+        self.local_hashes.retain(|_, (_, unchecked)| !unchecked.is_empty());
+        match error {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::LoopbackTransport;
+
+    #[derive(Default)]
+    struct Log {
+        pending: Vec<String>,
+        applied: Vec<String>,
+    }
+
+    impl Simulation for Log {
+        type Input = String;
+
+        fn enqueue_action(&mut self, input: String) {
+            self.pending.push(input);
+        }
+
+        fn enqueue_action_as(&mut self, issuer: Issuer, input: String) {
+            self.enqueue_action(format!("{}: {}", issuer, input));
+        }
+
+        fn process_actions(&mut self) {
+            self.applied.append(&mut self.pending);
+        }
+
+        fn state_hash(&self) -> u64 {
+            crate::simulation::hash_entry("log", &self.applied.len(), &self.applied)
+        }
+    }
+
+    #[test]
+    fn peers_apply_inputs_in_the_same_order() {
+        let mut sessions: Vec<_> = LoopbackTransport::network(2)
+            .into_iter()
+            .zip([(1, (1, 2)), (2, (0, 1))])
+            .map(|(transport, (player, peer))| LockstepSession::new(Log::default(), transport, player, [peer]))
+            .collect();
+
+        sessions[1].submit("b1".to_string());
+        sessions[0].submit("a1".to_string());
+        sessions[0].submit("a2".to_string());
+        for session in &mut sessions {
+            session.end_turn().unwrap();
+        }
+        for session in &mut sessions {
+            assert_eq!(session.wait_for_turn(Duration::from_secs(1)).unwrap(), 0);
+        }
+        for session in &mut sessions {
+            session.poll().unwrap();
+            assert_eq!(session.simulation().applied, vec!["player 1: a1", "player 1: a2", "player 2: b1"]);
+            assert_eq!(session.turn(), 1);
+        }
+    }
+
+    #[test]
+    fn detects_desyncs() {
+        let mut sessions: Vec<_> = LoopbackTransport::network(2)
+            .into_iter()
+            .zip([(1, (1, 2)), (2, (0, 1))])
+            .map(|(transport, (player, peer))| LockstepSession::new(Log::default(), transport, player, [peer]))
+            .collect();
+
+        sessions[0].simulation_mut().applied.push("cheat".to_string());
+        for session in &mut sessions {
+            session.end_turn().unwrap();
+        }
+        sessions[0].wait_for_turn(Duration::from_secs(1)).unwrap();
+        sessions[1].wait_for_turn(Duration::from_secs(1)).unwrap();
+
+        assert!(matches!(sessions[0].poll(), Err(SessionError::Desync { turn: 0, player: 2, .. })));
+    }
+
+    #[test]
+    fn rejects_inputs_sent_twice() {
+        let mut network = LoopbackTransport::network(2).into_iter();
+        let mut session = LockstepSession::new(Log::default(), network.next().unwrap(), 1, [(1, 2)]);
+        let mut remote = network.next().unwrap();
+
+        remote.send(LockstepMessage::Inputs { turn: 0, inputs: vec!["b1".to_string()] }).unwrap();
+        remote.send(LockstepMessage::Inputs { turn: 0, inputs: vec!["b2".to_string()] }).unwrap();
+        assert!(matches!(session.poll(), Err(SessionError::Duplicate { turn: 0, player: 2 })));
+
+        session.end_turn().unwrap();
+        assert_eq!(session.poll().unwrap(), Some(0));
+        assert_eq!(session.simulation().applied, vec!["player 2: b1"]);
+
+        remote.send(LockstepMessage::Inputs { turn: 0, inputs: vec!["b3".to_string()] }).unwrap();
+        assert!(matches!(session.poll(), Err(SessionError::Duplicate { turn: 0, player: 2 })));
+        assert!(session.inputs.is_empty());
+
+        remote.send(LockstepMessage::Hash { turn: 1, hash: 7 }).unwrap();
+        remote.send(LockstepMessage::Hash { turn: 1, hash: 7 }).unwrap();
+        assert!(matches!(session.poll(), Err(SessionError::Duplicate { turn: 1, player: 2 })));
+        assert_eq!(session.remote_hashes.len(), 1);
+    }
+
+    #[test]
+    fn rejects_turns_too_far_ahead() {
+        let mut network = LoopbackTransport::network(2).into_iter();
+        let mut session = LockstepSession::new(Log::default(), network.next().unwrap(), 1, [(1, 2)]);
+        let mut remote = network.next().unwrap();
+
+        remote.send(LockstepMessage::Inputs { turn: 1, inputs: vec!["b1".to_string()] }).unwrap();
+        remote.send(LockstepMessage::Inputs { turn: 2, inputs: vec!["b2".to_string()] }).unwrap();
+        assert!(matches!(session.poll(), Err(SessionError::TooFarAhead { turn: 2, player: 2 })));
+        remote.send(LockstepMessage::Hash { turn: u64::MAX, hash: 7 }).unwrap();
+        assert!(matches!(session.poll(), Err(SessionError::TooFarAhead { turn: u64::MAX, player: 2 })));
+
+        assert_eq!(session.inputs.keys().collect::<Vec<_>>(), vec![&1]);
+        assert!(session.remote_hashes.is_empty());
+    }
+
+    #[test]
+    fn forgets_turns_once_every_hash_is_checked() {
+        let mut sessions: Vec<_> = LoopbackTransport::network(2)
+            .into_iter()
+            .zip([(1, (1, 2)), (2, (0, 1))])
+            .map(|(transport, (player, peer))| LockstepSession::new(Log::default(), transport, player, [peer]))
+            .collect();
+
+        for _ in 0..3 {
+            for session in &mut sessions {
+                session.end_turn().unwrap();
+            }
+            for session in &mut sessions {
+                session.wait_for_turn(Duration::from_secs(1)).unwrap();
+            }
+        }
+        for session in &mut sessions {
+            session.poll().unwrap();
+            assert!(session.inputs.is_empty());
+            assert!(session.local_hashes.is_empty());
+            assert!(session.remote_hashes.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_messages_from_unknown_peers() {
+        let mut network = LoopbackTransport::network(3).into_iter();
+        let mut session = LockstepSession::new(Log::default(), network.next().unwrap(), 1, [(1, 2)]);
+        let _known = network.next().unwrap();
+        let mut stranger = network.next().unwrap();
+
+        stranger.send(LockstepMessage::Inputs { turn: 0, inputs: vec!["forged".to_string()] }).unwrap();
+        assert!(matches!(session.poll(), Err(SessionError::UnknownPeer(2))));
+    }
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Call count and time spent in one step of `process_actions`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timing {
+    pub calls: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Timing {
+    pub fn record(&mut self, elapsed: Duration) {
+        self.calls += 1;
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::ZERO
+        } else {
+            self.total.div_f64(self.calls as f64)
+        }
+    }
+}
+
+/// Where `GameWorld::process_actions` spent its time, collected once
+/// `GameWorld::set_metrics` is enabled. Hooks are timed per list, rules one by one, in
+/// the order they were added. Its `Display` prints a table, slowest step first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Each processed action, from populating it to running the after-commit hooks.
+    pub actions: Timing,
+    pub populate: Timing,
+    pub rules: Vec<Timing>,
+    pub hooks_on_accepted: Timing,
+    pub hooks_on_rejected: Timing,
+    pub hooks_after_commit: Timing,
+    pub spatial_updates: Timing,
+}
+
+impl Metrics {
+    pub fn rule(&mut self, index: usize) -> &mut Timing {
+        if self.rules.len() <= index {
+            self.rules.resize(index + 1, Timing::default());
+        }
+        &mut self.rules[index]
+    }
+
+    /// The rule with the largest total time, if any ran.
+    pub fn slowest_rule(&self) -> Option<(usize, &Timing)> {
+        self.rules
+            .iter()
+            .enumerate()
+            .filter(|(_, timing)| timing.calls > 0)
+            .max_by_key(|(_, timing)| timing.total)
+    }
+
+    fn steps(&self) -> Vec<(String, &Timing)> {
+        let mut steps = vec![("populate".to_string(), &self.populate)];
+        steps.extend(self.rules.iter().enumerate().map(|(index, timing)| (format!("rule {}", index), timing)));
+        steps.push(("hooks on accepted".to_string(), &self.hooks_on_accepted));
+        steps.push(("hooks on rejected".to_string(), &self.hooks_on_rejected));
+        steps.push(("hooks after commit".to_string(), &self.hooks_after_commit));
+        steps.push(("spatial updates".to_string(), &self.spatial_updates));
+        steps
+    }
+}
+
+impl fmt::Display for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut steps = self.steps();
+        steps.sort_by_key(|(_, timing)| std::cmp::Reverse(timing.total));
+        writeln!(f, "{:<20} {:>10} {:>12} {:>12} {:>12}", "step", "calls", "total", "mean", "max")?;
+        for (name, timing) in std::iter::once(("actions".to_string(), &self.actions)).chain(steps) {
+            writeln!(
+                f,
+                "{:<20} {:>10} {:>12} {:>12} {:>12}",
+                name,
+                timing.calls,
+                format!("{:.3?}", timing.total),
+                format!("{:.3?}", timing.mean()),
+                format!("{:.3?}", timing.max),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Times a step when metrics are enabled.
+#[doc(hidden)]
+pub struct Stopwatch(Option<Instant>);
+
+impl Stopwatch {
+    pub fn start(enabled: bool) -> Self {
+        Stopwatch(enabled.then(Instant::now))
+    }
+
+    pub fn stop(self, timing: Option<&mut Timing>) {
+        if let (Some(started), Some(timing)) = (self.0, timing) {
+            timing.record(started.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_report_the_slowest_rule() {
+        let mut metrics = Metrics::default();
+        metrics.rule(2).record(Duration::from_millis(3));
+        metrics.rule(0).record(Duration::from_millis(1));
+        metrics.rule(0).record(Duration::from_millis(3));
+
+        assert_eq!(metrics.rules.len(), 3);
+        assert_eq!(metrics.rules[0].mean(), Duration::from_millis(2));
+        assert_eq!(metrics.rules[0].max, Duration::from_millis(3));
+        assert_eq!(metrics.slowest_rule().map(|(index, _)| index), Some(0));
+
+        let report = metrics.to_string();
+        assert!(report.find("rule 0").unwrap() < report.find("rule 2").unwrap());
+    }
+}
@@ -0,0 +1,173 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::grid::{Connectivity, GridCell};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path<C> {
+    pub cells: Vec<C>,
+    pub cost: u32,
+}
+
+/// Finds the cheapest path from `start` to `goal` with A*.
+///
+/// `step_cost(from, to)` returns the cost of moving between two adjacent cells, or
+/// `None` if `to` can't be entered from `from`. Costs should be at least 1 per step
+/// for the returned path to be the cheapest one.
+///
+/// The search only ends once the goal is reached or every cell reachable from `start`
+/// was visited, so it doesn't end on an unbounded grid whose goal is unreachable. Unless
+/// `step_cost` keeps paths within a bounded area, use `find_path_within` with a budget.
+pub fn find_path<C, F>(start: C, goal: C, connectivity: Connectivity, step_cost: F) -> Option<Path<C>>
+where
+    C: GridCell,
+    F: FnMut(&C, &C) -> Option<u32>,
+{
+    find_path_within(start, goal, u32::MAX, connectivity, step_cost)
+}
+
+/// Like `find_path`, but gives up on paths costing more than `budget`.
+pub fn find_path_within<C, F>(start: C, goal: C, budget: u32, connectivity: Connectivity, mut step_cost: F) -> Option<Path<C>>
+where
+    C: GridCell,
+    F: FnMut(&C, &C) -> Option<u32>,
+{
+    // Cells are kept out of the heap so they don't need to be `Ord`; ties are broken by
+    // insertion order, which keeps the search deterministic.
+    let mut open = BinaryHeap::new();
+    let mut nodes = vec![start];
+    let mut came_from: HashMap<C, C> = HashMap::new();
+    let mut best_cost: HashMap<C, u32> = HashMap::new();
+
+    best_cost.insert(start, 0);
+    open.push(Reverse((start.distance(&goal, connectivity), 0, 0)));
+
+    while let Some(Reverse((_, node, cost))) = open.pop() {
+        let cell = nodes[node];
+
+        if cell == goal {
+            return Some(Path {
+                cells: rebuild_path(&came_from, start, goal),
+                cost,
+            });
+        }
+
+        if best_cost.get(&cell).is_some_and(|&best| cost > best) {
+            continue;
+        }
+
+        for neighbor in cell.neighbors(connectivity) {
+            let Some(step) = step_cost(&cell, &neighbor) else {
+                continue;
+            };
+            let neighbor_cost = cost.saturating_add(step);
+            if neighbor_cost > budget {
+                continue;
+            }
+            if best_cost.get(&neighbor).is_none_or(|&best| neighbor_cost < best) {
+                best_cost.insert(neighbor, neighbor_cost);
+                came_from.insert(neighbor, cell);
+                nodes.push(neighbor);
+                let estimate = neighbor_cost.saturating_add(neighbor.distance(&goal, connectivity));
+                open.push(Reverse((estimate, nodes.len() - 1, neighbor_cost)));
+            }
+        }
+    }
+
+    None
+}
+
+/// Returns every cell reachable from `start` without spending more than `budget`,
+/// along with the cheapest cost to reach it.
+pub fn reachable<C, F>(start: C, budget: u32, connectivity: Connectivity, mut step_cost: F) -> HashMap<C, u32>
+where
+    C: GridCell,
+    F: FnMut(&C, &C) -> Option<u32>,
+{
+    let mut open = BinaryHeap::new();
+    let mut nodes = vec![start];
+    let mut best_cost: HashMap<C, u32> = HashMap::new();
+
+    best_cost.insert(start, 0);
+    open.push(Reverse((0, 0)));
+
+    while let Some(Reverse((cost, node))) = open.pop() {
+        let cell = nodes[node];
+
+        if best_cost.get(&cell).is_some_and(|&best| cost > best) {
+            continue;
+        }
+
+        for neighbor in cell.neighbors(connectivity) {
+            let Some(step) = step_cost(&cell, &neighbor) else {
+                continue;
+            };
+            let neighbor_cost = cost.saturating_add(step);
+            if neighbor_cost > budget {
+                continue;
+            }
+            if best_cost.get(&neighbor).is_none_or(|&best| neighbor_cost < best) {
+                best_cost.insert(neighbor, neighbor_cost);
+                nodes.push(neighbor);
+                open.push(Reverse((neighbor_cost, nodes.len() - 1)));
+            }
+        }
+    }
+
+    best_cost
+}
+
+fn rebuild_path<C: GridCell>(came_from: &HashMap<C, C>, start: C, goal: C) -> Vec<C> {
+    let mut cells = vec![goal];
+    let mut current = goal;
+    while current != start {
+        current = came_from[&current];
+        cells.push(current);
+    }
+    cells.reverse();
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn walls() -> HashSet<[i32; 2]> {
+        [[1, -1], [1, 0], [1, 1]].into_iter().collect()
+    }
+
+    fn step_cost(walls: &HashSet<[i32; 2]>) -> impl FnMut(&[i32; 2], &[i32; 2]) -> Option<u32> + '_ {
+        move |_, to| {
+            if walls.contains(to) || to[0].abs() > 5 || to[1].abs() > 5 {
+                None
+            } else {
+                Some(1)
+            }
+        }
+    }
+
+    #[test]
+    fn walks_around_walls() {
+        let walls = walls();
+        let path = find_path([0, 0], [2, 0], Connectivity::Four, step_cost(&walls)).unwrap();
+
+        assert_eq!(path.cost, 6);
+        assert_eq!(path.cells.first(), Some(&[0, 0]));
+        assert_eq!(path.cells.last(), Some(&[2, 0]));
+        assert!(path.cells.iter().all(|cell| !walls.contains(cell)));
+        assert!(find_path_within([0, 0], [2, 0], 5, Connectivity::Four, step_cost(&walls)).is_none());
+    }
+
+    #[test]
+    fn limits_reachable_cells_to_budget() {
+        let walls = walls();
+        let cells = reachable([0, 0], 2, Connectivity::Four, step_cost(&walls));
+
+        assert_eq!(cells[&[0, 0]], 0);
+        assert_eq!(cells[&[0, 2]], 2);
+        assert!(!cells.contains_key(&[2, 0]));
+        assert!(!cells.contains_key(&[1, 0]));
+        assert!(cells.values().all(|&cost| cost <= 2));
+    }
+}
@@ -0,0 +1,313 @@
+use std::fmt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use wasmi::{Config, Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+#[derive(Debug)]
+pub enum PluginError {
+    Io(std::io::Error),
+    /// The module is not valid WebAssembly or can't be instantiated without imports.
+    Load(String),
+    /// The module lacks an export the plugin interface needs, or returned a bad pointer.
+    Interface(String),
+    /// The plugin trapped, ran out of fuel or exceeded its memory limit.
+    Trap(String),
+    /// A value could not be converted between Rust and the plugin's JSON.
+    Convert(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PluginError::Io(error) => write!(f, "could not read plugin: {}", error),
+            PluginError::Load(error) => write!(f, "could not load plugin: {}", error),
+            PluginError::Interface(error) => write!(f, "plugin does not follow the interface: {}", error),
+            PluginError::Trap(error) => write!(f, "plugin failed: {}", error),
+            PluginError::Convert(error) => write!(f, "could not convert plugin value: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+impl From<std::io::Error> for PluginError {
+    fn from(error: std::io::Error) -> Self {
+        PluginError::Io(error)
+    }
+}
+
+/// What a plugin rule decided about an action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginVerdict<T> {
+    pub accept: bool,
+    pub stop: bool,
+    pub actions: Vec<T>,
+}
+
+#[derive(serde::Deserialize)]
+struct Verdict {
+    #[serde(default = "accept_by_default")]
+    accept: bool,
+    #[serde(default)]
+    stop: bool,
+    #[serde(default)]
+    actions: Vec<Value>,
+}
+
+fn accept_by_default() -> bool {
+    true
+}
+
+/// A WebAssembly module used as a rule or a populate function.
+///
+/// The module gets no imports, so it can only compute. It exports its `memory`, an
+/// `alloc(len: i32) -> i32` function returning where the host may write `len` bytes, and
+/// some of:
+///
+/// - `rule(ptr: i32, len: i32) -> i64`, given `{"action": ..., "state": ...}`, returning
+///   `{"accept": bool, "stop": bool, "actions": [...]}` where every field is optional;
+/// - `populate(ptr: i32, len: i32) -> i64`, given `{"input": ..., "state": ..., "action": ...}`,
+///   returning the action filled in.
+///
+/// Arguments and results are UTF-8 JSON, laid out like `serde` does for `GameState`,
+/// `Action` and the action type. Results are returned as `ptr << 32 | len`.
+///
+/// Each call runs in a fresh instance with a fuel and a memory budget, so a plugin keeps
+/// no state between calls and a faulty one returns an error instead of hanging or
+/// crashing the host. Errors raised while the world runs a plugin reject the action, and
+/// are kept until `take_errors` is called.
+#[derive(Clone)]
+pub struct Plugin {
+    compiled: Arc<(Engine, Module)>,
+    components: Option<Vec<String>>,
+    fuel: u64,
+    memory: usize,
+    errors: Arc<Mutex<Vec<PluginError>>>,
+}
+
+impl Plugin {
+    pub const DEFAULT_FUEL: u64 = 10_000_000;
+    pub const DEFAULT_MEMORY: usize = 16 << 20;
+
+    /// Loads a module in the WebAssembly binary format.
+    pub fn load(wasm: &[u8]) -> Result<Self, PluginError> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, wasm).map_err(|error| PluginError::Load(error.to_string()))?;
+        Ok(Plugin {
+            compiled: Arc::new((engine, module)),
+            components: None,
+            fuel: Plugin::DEFAULT_FUEL,
+            memory: Plugin::DEFAULT_MEMORY,
+            errors: Arc::new(Mutex::new(Vec::new())),
+        })
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, PluginError> {
+        Plugin::load(&std::fs::read(path)?)
+    }
+
+    /// Only passes these components of the state to the plugin.
+    pub fn with_components(mut self, components: &[&str]) -> Self {
+        self.components = Some(components.iter().map(|component| component.to_string()).collect());
+        self
+    }
+
+    /// Limits the instructions each call may run.
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    /// Limits the memory, in bytes, each call may use.
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.memory = bytes;
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn components(&self) -> Option<&[String]> {
+        self.components.as_deref()
+    }
+
+    /// Errors are shared between clones, so a clone kept aside sees those of a plugin
+    /// registered on a `GameWorld`.
+    pub fn take_errors(&self) -> Vec<PluginError> {
+        std::mem::take(&mut *self.errors.lock().unwrap())
+    }
+
+    #[doc(hidden)]
+    pub fn record_error(&self, error: PluginError) {
+        self.errors.lock().unwrap().push(error);
+    }
+
+    pub fn rule<A, S, T>(&self, action: &A, state: &S) -> Result<PluginVerdict<T>, PluginError>
+    where
+        A: Serialize,
+        S: Serialize,
+        T: DeserializeOwned,
+    {
+        let argument = serde_json::json!({
+            "action": to_value(action)?,
+            "state": self.view(state)?,
+        });
+        let verdict: Verdict = from_value(self.call("rule", &argument)?)?;
+        Ok(PluginVerdict {
+            accept: verdict.accept,
+            stop: verdict.stop,
+            actions: verdict.actions.into_iter().map(from_value).collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// Calls `populate` with `empty_action` for the plugin to fill in.
+    pub fn populate<I, S, A>(&self, input: &I, state: &S, empty_action: &A) -> Result<A, PluginError>
+    where
+        I: Serialize,
+        S: Serialize,
+        A: Serialize + DeserializeOwned,
+    {
+        let argument = serde_json::json!({
+            "input": to_value(input)?,
+            "state": self.view(state)?,
+            "action": to_value(empty_action)?,
+        });
+        from_value(self.call("populate", &argument)?)
+    }
+
+    fn view<S: Serialize>(&self, state: &S) -> Result<Value, PluginError> {
+        let mut state = to_value(state)?;
+        if let (Some(components), Value::Object(state)) = (&self.components, &mut state) {
+            state.retain(|component, _| components.contains(component));
+        }
+        Ok(state)
+    }
+
+    fn call(&self, function: &str, argument: &Value) -> Result<Value, PluginError> {
+        let (engine, module) = &*self.compiled;
+        let mut store = Store::new(engine, StoreLimitsBuilder::new().memory_size(self.memory).build());
+        store.limiter(|limits: &mut StoreLimits| limits);
+        store.set_fuel(self.fuel).map_err(|error| PluginError::Load(error.to_string()))?;
+
+        let instance = Linker::<StoreLimits>::new(engine)
+            .instantiate(&mut store, module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|error| PluginError::Load(error.to_string()))?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| PluginError::Interface("no `memory` export".to_string()))?;
+        let interface = |export: &str, error: wasmi::Error| PluginError::Interface(format!("`{}`: {}", export, error));
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "alloc")
+            .map_err(|error| interface("alloc", error))?;
+        let function = instance
+            .get_typed_func::<(i32, i32), i64>(&store, function)
+            .map_err(|error| interface(function, error))?;
+
+        let argument = serde_json::to_vec(argument).map_err(|error| PluginError::Convert(error.to_string()))?;
+        let length = i32::try_from(argument.len()).map_err(|_| PluginError::Convert("argument too large".to_string()))?;
+        let trap = |error: wasmi::Error| PluginError::Trap(error.to_string());
+        let pointer = alloc.call(&mut store, length).map_err(trap)?;
+        let out_of_bounds = |_| PluginError::Interface("pointer out of bounds".to_string());
+        memory.write(&mut store, pointer as u32 as usize, &argument).map_err(out_of_bounds)?;
+
+        let result = function.call(&mut store, (pointer, length)).map_err(trap)?;
+        let (pointer, length) = ((result >> 32) as u32 as usize, result as u32 as usize);
+        // Read in place and bounds-checked, so a bad length can't make the host allocate.
+        let bytes = pointer
+            .checked_add(length)
+            .and_then(|end| memory.data(&store).get(pointer..end))
+            .ok_or_else(|| PluginError::Interface("result out of bounds".to_string()))?;
+        serde_json::from_slice(bytes).map_err(|error| PluginError::Convert(error.to_string()))
+    }
+}
+
+impl fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Plugin")
+            .field("components", &self.components)
+            .field("fuel", &self.fuel)
+            .field("memory", &self.memory)
+            .finish()
+    }
+}
+
+fn to_value<V: Serialize>(value: &V) -> Result<Value, PluginError> {
+    serde_json::to_value(value).map_err(|error| PluginError::Convert(error.to_string()))
+}
+
+fn from_value<V: DeserializeOwned>(value: Value) -> Result<V, PluginError> {
+    serde_json::from_value(value).map_err(|error| PluginError::Convert(error.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Answers every call with the JSON in its data segment.
+    fn constant_plugin(export: &str, answer: &str) -> Plugin {
+        let wat = format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{answer}")
+                (func (export "alloc") (param $len i32) (result i32)
+                    (local $missing i32)
+                    (local.set $missing (i32.sub
+                        (i32.div_u (i32.add (i32.add (i32.const 1024) (local.get $len)) (i32.const 65535)) (i32.const 65536))
+                        (memory.size)))
+                    (if (i32.gt_s (local.get $missing) (i32.const 0))
+                        (then (drop (memory.grow (local.get $missing)))))
+                    (i32.const 1024))
+                (func (export "{export}") (param i32 i32) (result i64)
+                    (i64.const {length})))"#,
+            answer = answer.replace('"', "\\\""),
+            export = export,
+            length = answer.len(),
+        );
+        Plugin::load(&wat::parse_str(wat).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn plugins_return_verdicts_and_actions() {
+        let plugin = constant_plugin("rule", r#"{"accept": false, "actions": ["Retreat"]}"#);
+        let verdict: PluginVerdict<String> = plugin.rule(&"action", &vec![0u8; 100_000]).unwrap();
+        assert_eq!(verdict, PluginVerdict { accept: false, stop: false, actions: vec!["Retreat".to_string()] });
+
+        let plugin = constant_plugin("populate", "[1, 2]");
+        assert_eq!(plugin.populate(&"input", &"state", &Vec::<u8>::new()).unwrap(), vec![1, 2]);
+        assert!(matches!(plugin.rule::<_, _, String>(&"action", &"state"), Err(PluginError::Interface(_))));
+    }
+
+    #[test]
+    fn runaway_plugins_run_out_of_fuel() {
+        let wat = r#"(module
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) (i32.const 0))
+            (func (export "rule") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (i64.const 0)))"#;
+        let plugin = Plugin::load(&wat::parse_str(wat).unwrap()).unwrap().with_fuel(1_000);
+
+        assert!(matches!(plugin.rule::<_, _, String>(&"action", &"state"), Err(PluginError::Trap(_))));
+    }
+
+    #[test]
+    fn results_must_lie_in_memory() {
+        for result in ["0xFFFF_FFFF", "0x0000_FFFF_0000_0010"] {
+            let wat = format!(
+                r#"(module
+                    (memory (export "memory") 1)
+                    (func (export "alloc") (param i32) (result i32) (i32.const 0))
+                    (func (export "rule") (param i32 i32) (result i64) (i64.const {})))"#,
+                result
+            );
+            let plugin = Plugin::load(&wat::parse_str(wat).unwrap()).unwrap();
+
+            assert!(matches!(plugin.rule::<_, _, String>(&"action", &"state"), Err(PluginError::Interface(_))));
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::ops::{Mul, Sub};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionTransition {
+    Enter,
+    Exit,
+}
+
+pub trait Area<P> {
+    fn contains(&self, point: &P) -> bool;
+}
+
+/// A point on a plane, used by the built-in region shapes.
+pub trait Planar {
+    type Scalar: Copy + Default + PartialOrd + Sub<Output = Self::Scalar> + Mul<Output = Self::Scalar>;
+
+    fn x(&self) -> Self::Scalar;
+    fn y(&self) -> Self::Scalar;
+}
+
+macro_rules! impl_planar {
+    ($( $scalar:ty ),*) => {
+        $(
+            impl Planar for [$scalar; 2] {
+                type Scalar = $scalar;
+
+                fn x(&self) -> $scalar {
+                    self[0]
+                }
+
+                fn y(&self) -> $scalar {
+                    self[1]
+                }
+            }
+        )*
+    };
+}
+
+impl_planar!(i8, i16, i32, i64, isize, f32, f64);
+
+/// An axis-aligned rectangle, edges included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rect<P> {
+    pub min: P,
+    pub max: P,
+}
+
+impl<P: Planar> Area<P> for Rect<P> {
+    fn contains(&self, point: &P) -> bool {
+        self.min.x() <= point.x() && point.x() <= self.max.x() && self.min.y() <= point.y() && point.y() <= self.max.y()
+    }
+}
+
+/// A simple polygon given by its vertices in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon<P> {
+    pub vertices: Vec<P>,
+}
+
+impl<P: Planar> Area<P> for Polygon<P> {
+    fn contains(&self, point: &P) -> bool {
+        let zero = P::Scalar::default();
+        let mut winding = 0i32;
+        for (index, a) in self.vertices.iter().enumerate() {
+            let b = &self.vertices[(index + 1) % self.vertices.len()];
+            let cross = (b.x() - a.x()) * (point.y() - a.y()) - (point.x() - a.x()) * (b.y() - a.y());
+            let on_segment = cross == zero
+                && partial_min(a.x(), b.x()) <= point.x()
+                && point.x() <= partial_max(a.x(), b.x())
+                && partial_min(a.y(), b.y()) <= point.y()
+                && point.y() <= partial_max(a.y(), b.y());
+            if on_segment {
+                return true;
+            }
+            if a.y() <= point.y() {
+                if b.y() > point.y() && cross > zero {
+                    winding += 1;
+                }
+            } else if b.y() <= point.y() && cross < zero {
+                winding -= 1;
+            }
+        }
+        winding != 0
+    }
+}
+
+impl<P, F: Fn(&P) -> bool> Area<P> for F {
+    fn contains(&self, point: &P) -> bool {
+        self(point)
+    }
+}
+
+fn partial_min<S: PartialOrd>(a: S, b: S) -> S {
+    if b < a {
+        b
+    } else {
+        a
+    }
+}
+
+fn partial_max<S: PartialOrd>(a: S, b: S) -> S {
+    if b > a {
+        b
+    } else {
+        a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_includes_edges() {
+        let rect = Rect { min: [0, 0], max: [2, 3] };
+
+        assert!(rect.contains(&[0, 0]));
+        assert!(rect.contains(&[2, 3]));
+        assert!(!rect.contains(&[3, 1]));
+    }
+
+    #[test]
+    fn polygon_handles_concave_shapes() {
+        let l_shape = Polygon {
+            vertices: vec![[0, 0], [4, 0], [4, 1], [1, 1], [1, 4], [0, 4]],
+        };
+
+        assert!(l_shape.contains(&[0, 3]));
+        assert!(l_shape.contains(&[3, 0]));
+        assert!(l_shape.contains(&[1, 2]));
+        assert!(!l_shape.contains(&[3, 3]));
+        assert!(!l_shape.contains(&[5, 0]));
+    }
+}
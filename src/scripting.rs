@@ -0,0 +1,478 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, RwLock};
+
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde::de::{self, DeserializeOwned, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer, Serialize};
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(std::io::Error),
+    Compile(String),
+    Runtime(String),
+    /// A value could not be converted between Rust and the script.
+    Convert(String),
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptError::Io(error) => write!(f, "could not read script: {}", error),
+            ScriptError::Compile(error) => write!(f, "could not compile script: {}", error),
+            ScriptError::Runtime(error) => write!(f, "script failed: {}", error),
+            ScriptError::Convert(error) => write!(f, "could not convert script value: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<std::io::Error> for ScriptError {
+    fn from(error: std::io::Error) -> Self {
+        ScriptError::Io(error)
+    }
+}
+
+impl From<Box<EvalAltResult>> for ScriptError {
+    fn from(error: Box<EvalAltResult>) -> Self {
+        ScriptError::Runtime(error.to_string())
+    }
+}
+
+/// What a rule script decided about an action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleVerdict<T> {
+    pub accept: bool,
+    pub stop: bool,
+    pub actions: Vec<T>,
+}
+
+/// A Rhai script used as a rule, a populate function or a hook.
+///
+/// Values cross into the script through serde: the state and actions become object maps
+/// keyed by component name and then by id, as strings. A script defines some of:
+///
+/// - `fn rule(action, state)` returning `true`/`false`, or a map with optional `accept`,
+///   `stop` and `actions` (follow-up actions, e.g. `#{ Move: [1, 2, 3] }`) fields;
+/// - `fn populate(input, state, action)` returning `action` once filled in with
+///   `action.set_component("health", id, value)` and `action.remove_component("health", id)`;
+/// - `fn hook(action, state)` returning an array of events.
+///
+/// `state.get_component("health", id)` reads the committed state, and
+/// `future_component(state, action, "health", id)` reads it as if the action was
+/// committed, like `FutureState`.
+///
+/// Each call may run `DEFAULT_OPERATIONS` operations and nest `DEFAULT_CALL_LEVELS`
+/// function calls, so a runaway script returns an error instead of hanging the host.
+/// Expressions nested deeper than `MAX_EXPR_DEPTH` don't compile.
+///
+/// Clones share the compiled script and its limits, so a clone kept aside can `reload` a
+/// script already registered on a `GameWorld`. Errors raised while the world runs a
+/// script reject the action or skip the hook, and are kept until `take_errors` is called.
+#[derive(Clone)]
+pub struct Script {
+    inner: Arc<ScriptInner>,
+    components: Option<Vec<String>>,
+}
+
+struct ScriptInner {
+    engine: RwLock<Engine>,
+    path: Option<PathBuf>,
+    ast: RwLock<AST>,
+    errors: Mutex<Vec<ScriptError>>,
+}
+
+impl Script {
+    pub const DEFAULT_OPERATIONS: u64 = 1_000_000;
+    pub const DEFAULT_CALL_LEVELS: usize = 64;
+    pub const MAX_EXPR_DEPTH: usize = 64;
+
+    pub fn compile(source: &str) -> Result<Self, ScriptError> {
+        Script::new(source, None)
+    }
+
+    pub fn from_file(path: impl Into<PathBuf>) -> Result<Self, ScriptError> {
+        let path = path.into();
+        let source = std::fs::read_to_string(&path)?;
+        Script::new(&source, Some(path))
+    }
+
+    fn new(source: &str, path: Option<PathBuf>) -> Result<Self, ScriptError> {
+        let engine = engine();
+        let ast = engine.compile(source).map_err(|error| ScriptError::Compile(error.to_string()))?;
+        Ok(Script {
+            inner: Arc::new(ScriptInner {
+                engine: RwLock::new(engine),
+                path,
+                ast: RwLock::new(ast),
+                errors: Mutex::new(Vec::new()),
+            }),
+            components: None,
+        })
+    }
+
+    /// Only passes these components of the state to the script.
+    pub fn with_components(mut self, components: &[&str]) -> Self {
+        self.components = Some(components.iter().map(|component| component.to_string()).collect());
+        self
+    }
+
+    /// Limits the operations each call may run.
+    pub fn with_max_operations(self, operations: u64) -> Self {
+        self.inner.engine.write().unwrap().set_max_operations(operations);
+        self
+    }
+
+    /// Limits how deeply each call may nest function calls.
+    pub fn with_max_call_levels(self, levels: usize) -> Self {
+        self.inner.engine.write().unwrap().set_max_call_levels(levels);
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn components(&self) -> Option<&[String]> {
+        self.components.as_deref()
+    }
+
+    /// Reads the script file again. The previous version stays in use if it fails to compile.
+    pub fn reload(&self) -> Result<(), ScriptError> {
+        let Some(path) = &self.inner.path else {
+            return Ok(());
+        };
+        let source = std::fs::read_to_string(path)?;
+        let ast = self.inner.engine.read().unwrap().compile(source).map_err(|error| ScriptError::Compile(error.to_string()))?;
+        *self.inner.ast.write().unwrap() = ast;
+        Ok(())
+    }
+
+    pub fn take_errors(&self) -> Vec<ScriptError> {
+        std::mem::take(&mut *self.inner.errors.lock().unwrap())
+    }
+
+    #[doc(hidden)]
+    pub fn record_error(&self, error: ScriptError) {
+        self.inner.errors.lock().unwrap().push(error);
+    }
+
+    pub fn rule<A, S, T>(&self, action: &A, state: &S) -> Result<RuleVerdict<T>, ScriptError>
+    where
+        A: Serialize,
+        S: Serialize,
+        T: DeserializeOwned,
+    {
+        let result = self.call("rule", (to_dynamic(action)?, to_dynamic(state)?))?;
+        if let Some(accept) = result.clone().try_cast::<bool>() {
+            return Ok(RuleVerdict { accept, stop: false, actions: Vec::new() });
+        }
+        let mut verdict = result
+            .try_cast::<Map>()
+            .ok_or_else(|| ScriptError::Convert("`rule` must return a bool or a map".to_string()))?;
+        let flag = |verdict: &mut Map, name: &str, default: bool| match verdict.remove(name) {
+            Some(value) => value
+                .as_bool()
+                .map_err(|_| ScriptError::Convert(format!("`{}` must be a bool", name))),
+            None => Ok(default),
+        };
+        Ok(RuleVerdict {
+            accept: flag(&mut verdict, "accept", true)?,
+            stop: flag(&mut verdict, "stop", false)?,
+            actions: match verdict.remove("actions") {
+                Some(actions) => from_dynamic(&actions)?,
+                None => Vec::new(),
+            },
+        })
+    }
+
+    /// Calls `populate` with `empty_action` for the script to fill in.
+    pub fn populate<I, S, A>(&self, input: &I, state: &S, empty_action: &A) -> Result<A, ScriptError>
+    where
+        I: Serialize,
+        S: Serialize,
+        A: Serialize + DeserializeOwned,
+    {
+        let action = self.call("populate", (to_dynamic(input)?, to_dynamic(state)?, to_dynamic(empty_action)?))?;
+        from_dynamic(&action)
+    }
+
+    pub fn hook<A, S, E>(&self, action: &A, state: &S) -> Result<Vec<E>, ScriptError>
+    where
+        A: Serialize,
+        S: Serialize,
+        E: DeserializeOwned,
+    {
+        let events = self.call("hook", (to_dynamic(action)?, to_dynamic(state)?))?;
+        from_dynamic(&events)
+    }
+
+    fn call(&self, function: &str, args: impl rhai::FuncArgs) -> Result<Dynamic, ScriptError> {
+        let ast = self.inner.ast.read().unwrap();
+        Ok(self.inner.engine.read().unwrap().call_fn(&mut Scope::new(), &ast, function, args)?)
+    }
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Script")
+            .field("path", &self.inner.path)
+            .field("components", &self.components)
+            .finish()
+    }
+}
+
+/// Checks that ids of type `I` can key the maps scripts see: strings, numbers or newtypes
+/// around them. Composite ids such as tuples can't.
+pub fn check_id_type<I: DeserializeOwned>() -> Result<(), ScriptError> {
+    I::deserialize(KeyProbe).map(|_| ()).map_err(|error| ScriptError::Convert(error.to_string()))
+}
+
+/// Deserializes the simplest value of a type if it is a string, a number or a newtype
+/// around one, and fails otherwise.
+struct KeyProbe;
+
+impl<'de> Deserializer<'de> for KeyProbe {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("ids must be strings, numbers or newtypes around them"))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i8(0)
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i16(0)
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i32(0)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u8(0)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u16(0)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u32(0)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_char('0')
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_str("")
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 f32 f64 bytes byte_buf option unit unit_struct seq tuple tuple_struct map
+        struct enum identifier ignored_any
+    }
+}
+
+fn to_dynamic<V: Serialize>(value: &V) -> Result<Dynamic, ScriptError> {
+    // Going through JSON turns integer ids into string map keys, which Rhai requires.
+    let value = serde_json::to_value(value).map_err(|error| ScriptError::Convert(error.to_string()))?;
+    rhai::serde::to_dynamic(value).map_err(|error| ScriptError::Convert(error.to_string()))
+}
+
+fn from_dynamic<V: DeserializeOwned>(value: &Dynamic) -> Result<V, ScriptError> {
+    let value: serde_json::Value = rhai::serde::from_dynamic(value).map_err(|error| ScriptError::Convert(error.to_string()))?;
+    serde_json::from_value(value).map_err(|error| ScriptError::Convert(error.to_string()))
+}
+
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(Script::DEFAULT_OPERATIONS)
+        .set_max_call_levels(Script::DEFAULT_CALL_LEVELS)
+        .set_max_expr_depths(Script::MAX_EXPR_DEPTH, Script::MAX_EXPR_DEPTH);
+    engine.register_fn("get_component", |state: &mut Map, component: &str, id: Dynamic| {
+        component_value(state, component, &id)
+    });
+    engine.register_fn(
+        "future_component",
+        |state: Map, action: Map, component: &str, id: Dynamic| -> Result<Dynamic, Box<EvalAltResult>> {
+            // Removals are committed after updates, so they win.
+            let removals = section(&action, "removals")?;
+            let removed = removals
+                .get(component)
+                .and_then(|ids| ids.read_lock::<rhai::Array>().map(|ids| ids.iter().any(|removed| removed.to_string() == id.to_string())))
+                .unwrap_or(false);
+            if removed {
+                return Ok(Dynamic::UNIT);
+            }
+            let updated = component_value(&section(&action, "updates")?, component, &id)?;
+            if updated.is_unit() {
+                component_value(&state, component, &id)
+            } else {
+                Ok(updated)
+            }
+        },
+    );
+    engine.register_fn(
+        "set_component",
+        |action: &mut Map, component: &str, id: Dynamic, value: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            with_component(action, "updates", component, |values| {
+                let mut values = values.write_lock::<Map>().ok_or("updates must be maps")?;
+                values.insert(id.to_string().into(), value);
+                Ok(())
+            })
+        },
+    );
+    engine.register_fn(
+        "remove_component",
+        |action: &mut Map, component: &str, id: Dynamic| -> Result<(), Box<EvalAltResult>> {
+            with_component(action, "removals", component, |ids| {
+                let mut ids = ids.write_lock::<rhai::Array>().ok_or("removals must be arrays")?;
+                ids.push(id);
+                Ok(())
+            })
+        },
+    );
+    engine
+}
+
+fn section(action: &Map, name: &str) -> Result<Map, Box<EvalAltResult>> {
+    action
+        .get(name)
+        .and_then(|section| section.clone().try_cast::<Map>())
+        .ok_or_else(|| format!("action has no `{}`", name).into())
+}
+
+fn component_value(state: &Map, component: &str, id: &Dynamic) -> Result<Dynamic, Box<EvalAltResult>> {
+    let values = state
+        .get(component)
+        .and_then(|values| values.read_lock::<Map>().map(|values| values.get(id.to_string().as_str()).cloned()))
+        .ok_or_else(|| format!("unknown component `{}`", component))?;
+    Ok(values.unwrap_or(Dynamic::UNIT))
+}
+
+fn with_component(
+    action: &mut Map,
+    section: &str,
+    component: &str,
+    f: impl FnOnce(&mut Dynamic) -> Result<(), Box<EvalAltResult>>,
+) -> Result<(), Box<EvalAltResult>> {
+    let mut section = action
+        .get_mut(section)
+        .and_then(|section| section.write_lock::<Map>())
+        .ok_or_else(|| format!("action has no `{}`", section))?;
+    let values = section
+        .get_mut(component)
+        .ok_or_else(|| format!("unknown component `{}`", component))?;
+    f(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct State {
+        health: HashMap<u32, i32>,
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct Removals {
+        health: HashSet<u32>,
+    }
+
+    #[derive(Debug, Default, Serialize, Deserialize)]
+    struct Action {
+        updates: State,
+        removals: Removals,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Input {
+        Hit { target: u32, damage: i32 },
+        Heal(u32),
+    }
+
+    const SCRIPT: &str = r#"
+        fn populate(input, state, action) {
+            let health = state.get_component("health", input.Hit.target);
+            action.set_component("health", input.Hit.target, health - input.Hit.damage);
+            action
+        }
+
+        fn rule(action, state) {
+            let health = future_component(state, action, "health", 1);
+            if health <= 0 {
+                #{ accept: false, actions: [#{ Heal: 1 }] }
+            } else {
+                true
+            }
+        }
+    "#;
+
+    #[test]
+    fn scripts_populate_and_judge_actions() {
+        let script = Script::compile(SCRIPT).unwrap();
+        let state = State { health: HashMap::from([(1, 5)]) };
+
+        let action: Action = script.populate(&Input::Hit { target: 1, damage: 3 }, &state, &Action::default()).unwrap();
+        assert_eq!(action.updates.health, HashMap::from([(1, 2)]));
+        let verdict: RuleVerdict<Input> = script.rule(&action, &state).unwrap();
+        assert!(verdict.accept && verdict.actions.is_empty());
+
+        let action: Action = script.populate(&Input::Hit { target: 1, damage: 9 }, &state, &Action::default()).unwrap();
+        let verdict: RuleVerdict<Input> = script.rule(&action, &state).unwrap();
+        assert!(!verdict.accept);
+        assert_eq!(verdict.actions, vec![Input::Heal(1)]);
+    }
+
+    #[test]
+    fn runaway_scripts_hit_their_limits() {
+        let script = Script::compile("fn rule(action, state) { loop {} }").unwrap().with_max_operations(1_000);
+        assert!(matches!(script.rule::<_, _, Input>(&"action", &"state"), Err(ScriptError::Runtime(_))));
+
+        let script = Script::compile("fn deep(n) { deep(n + 1) } fn rule(action, state) { deep(0) }").unwrap();
+        assert!(matches!(script.rule::<_, _, Input>(&"action", &"state"), Err(ScriptError::Runtime(_))));
+
+        let nested = format!("fn rule(action, state) {{ {}1{} }}", "(".repeat(200), ")".repeat(200));
+        assert!(matches!(Script::compile(&nested), Err(ScriptError::Compile(_))));
+    }
+
+    #[derive(Deserialize)]
+    struct Named(#[allow(dead_code)] String);
+
+    #[test]
+    fn ids_must_be_map_keys() {
+        assert!(check_id_type::<u32>().is_ok());
+        assert!(check_id_type::<String>().is_ok());
+        assert!(check_id_type::<Named>().is_ok());
+        assert!(matches!(check_id_type::<(u32, String)>(), Err(ScriptError::Convert(_))));
+        assert!(matches!(check_id_type::<[u32; 2]>(), Err(ScriptError::Convert(_))));
+    }
+}
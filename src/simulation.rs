@@ -0,0 +1,75 @@
+use std::fmt::{self, Debug, Write};
+use std::hash::Hasher;
+
+use crate::actor::Issuer;
+
+/// What the session layers need from a `GameWorld`. Implemented by `register_components!`.
+pub trait Simulation {
+    type Input;
+
+    fn enqueue_action(&mut self, input: Self::Input);
+
+    /// Enqueues an input on behalf of `issuer`. Session layers use this for inputs received
+    /// from peers, so its permissions must be checked when it is processed, unlike those
+    /// given to `enqueue_action`.
+    fn enqueue_action_as(&mut self, issuer: Issuer, input: Self::Input);
+
+    fn process_actions(&mut self);
+    fn state_hash(&self) -> u64;
+}
+
+/// FNV-1a, so state hashes are stable across processes, platforms and Rust versions.
+#[derive(Debug, Clone)]
+pub struct StateHasher(u64);
+
+impl StateHasher {
+    pub fn new() -> Self {
+        StateHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        StateHasher::new()
+    }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl Write for StateHasher {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        Hasher::write(self, s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Hashes one component entry through its `Debug` output. Entry hashes are meant to be
+/// summed, which makes the state hash independent of `HashMap` iteration order.
+pub fn hash_entry(component: &str, id: &dyn Debug, value: &dyn Debug) -> u64 {
+    let mut hasher = StateHasher::new();
+    let _ = write!(hasher, "{}|{:?}|{:?}", component, id, value);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_hashes_are_stable() {
+        assert_eq!(hash_entry("health", &1u32, &10i32), hash_entry("health", &1u32, &10i32));
+        assert_ne!(hash_entry("health", &1u32, &10i32), hash_entry("health", &2u32, &10i32));
+        assert_ne!(hash_entry("health", &1u32, &10i32), hash_entry("armor", &1u32, &10i32));
+    }
+}
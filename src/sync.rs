@@ -0,0 +1,360 @@
+use std::collections::{BTreeMap, VecDeque};
+
+#[cfg(feature = "tcp")]
+use serde::{Deserialize, Serialize};
+
+use crate::actor::{Issuer, PlayerId};
+use crate::simulation::Simulation;
+use crate::transport::{PeerId, Transport, TransportError};
+
+/// A `Simulation` whose committed changes can be recorded, replayed and undone.
+/// Implemented by `register_components!` with `Action` as the delta.
+pub trait Authoritative: Simulation {
+    type Delta;
+
+    /// Processes the pending inputs and returns every committed change, in order.
+    fn process_recording(&mut self) -> Vec<Self::Delta>;
+
+    /// Processes the pending inputs and returns the changes that undo them, in commit
+    /// order. Applying them in reverse restores the previous state.
+    fn process_reversible(&mut self) -> Vec<Self::Delta>;
+
+    /// Commits a change without running any rules or hooks.
+    fn apply_delta(&mut self, delta: Self::Delta);
+
+    /// Number of events emitted and not drained yet.
+    fn pending_events(&self) -> usize;
+
+    /// Drops the `count` most recent events, e.g. those of predictions about to be replayed.
+    fn discard_events(&mut self, count: usize);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "tcp", derive(Serialize, Deserialize))]
+pub enum SyncMessage<T, D> {
+    /// The server knows the player from the peer the input arrived from.
+    Input { sequence: u64, input: T },
+    Update { acks: Vec<(PlayerId, u64)>, deltas: Vec<D> },
+}
+
+/// Owns the authoritative simulation: it processes the inputs sent by clients and
+/// broadcasts the resulting changes along with the last input handled for each client.
+pub struct SyncServer<S, Tr> {
+    simulation: S,
+    transport: Tr,
+    players: BTreeMap<PeerId, PlayerId>,
+    acks: BTreeMap<PlayerId, u64>,
+}
+
+impl<S, Tr> SyncServer<S, Tr>
+where
+    S: Authoritative,
+    S::Delta: Clone,
+    Tr: Transport<SyncMessage<S::Input, S::Delta>>,
+{
+    /// `players` maps the peers of `transport` to the player each one plays as. Inputs
+    /// from other peers are dropped.
+    pub fn new(simulation: S, transport: Tr, players: impl IntoIterator<Item = (PeerId, PlayerId)>) -> Self {
+        SyncServer {
+            simulation,
+            transport,
+            players: players.into_iter().collect(),
+            acks: BTreeMap::new(),
+        }
+    }
+
+    pub fn simulation(&self) -> &S {
+        &self.simulation
+    }
+
+    /// Mutable access to the simulation, e.g. to enqueue server-side inputs before the
+    /// next `poll`.
+    pub fn simulation_mut(&mut self) -> &mut S {
+        &mut self.simulation
+    }
+
+    /// Processes the inputs received since the last poll and broadcasts the result.
+    /// Returns the number of inputs received.
+    pub fn poll(&mut self) -> Result<usize, TransportError> {
+        let mut received = 0;
+        while let Some((peer, message)) = self.transport.receive()? {
+            if let SyncMessage::Input { sequence, input } = message {
+                let Some(&player) = self.players.get(&peer) else {
+                    tracing::warn!(peer, "dropped input from unknown peer");
+                    continue;
+                };
+                // Denied inputs are acknowledged all the same, so the client drops its prediction.
+                self.simulation.enqueue_action_as(Issuer::Player(player), input);
+                self.acks.insert(player, sequence);
+                received += 1;
+            }
+        }
+
+        let deltas = self.simulation.process_recording();
+        if received > 0 || !deltas.is_empty() {
+            self.transport.send(SyncMessage::Update {
+                acks: self.acks.iter().map(|(&player, &sequence)| (player, sequence)).collect(),
+                deltas,
+            })?;
+        }
+        Ok(received)
+    }
+}
+
+/// Predicts the outcome of local inputs right away and reconciles with the server:
+/// when an update arrives, the predictions are undone, the authoritative changes are
+/// applied and the inputs the server has not handled yet are replayed on top.
+///
+/// Events emitted by a prediction are kept once the server has handled its input. Those
+/// of the inputs being replayed are discarded first, unless already drained, so replaying
+/// doesn't emit them twice.
+pub struct SyncClient<S: Authoritative, Tr> {
+    simulation: S,
+    transport: Tr,
+    player: PlayerId,
+    next_sequence: u64,
+    /// Inputs not handled by the server yet, with the number of events their prediction emitted.
+    unacknowledged: VecDeque<(u64, S::Input, usize)>,
+    predictions: Vec<S::Delta>,
+}
+
+impl<S, Tr> SyncClient<S, Tr>
+where
+    S: Authoritative,
+    S::Input: Clone,
+    Tr: Transport<SyncMessage<S::Input, S::Delta>>,
+{
+    pub fn new(simulation: S, transport: Tr, player: PlayerId) -> Self {
+        SyncClient {
+            simulation,
+            transport,
+            player,
+            next_sequence: 0,
+            unacknowledged: VecDeque::new(),
+            predictions: Vec::new(),
+        }
+    }
+
+    pub fn simulation(&self) -> &S {
+        &self.simulation
+    }
+
+    /// Mutable access to the simulation, e.g. to drain its events. Changes made outside
+    /// of the client are overwritten by the server's.
+    pub fn simulation_mut(&mut self) -> &mut S {
+        &mut self.simulation
+    }
+
+    pub fn player(&self) -> PlayerId {
+        self.player
+    }
+
+    /// Number of inputs sent that the server has not handled yet.
+    pub fn pending_inputs(&self) -> usize {
+        self.unacknowledged.len()
+    }
+
+    /// Sends `input` to the server and applies it locally without waiting.
+    pub fn predict(&mut self, input: S::Input) -> Result<(), TransportError> {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.transport.send(SyncMessage::Input {
+            sequence,
+            input: input.clone(),
+        })?;
+
+        let events = self.predict_locally(input.clone());
+        self.unacknowledged.push_back((sequence, input, events));
+        Ok(())
+    }
+
+    /// Returns the number of events emitted by the prediction.
+    fn predict_locally(&mut self, input: S::Input) -> usize {
+        let events = self.simulation.pending_events();
+        self.simulation.enqueue_action_as(Issuer::Player(self.player), input);
+        let undo = self.simulation.process_reversible();
+        self.predictions.extend(undo);
+        self.simulation.pending_events().saturating_sub(events)
+    }
+
+    /// Applies the updates received from the server. Returns the number of updates.
+    pub fn poll(&mut self) -> Result<usize, TransportError> {
+        let mut updates = Vec::new();
+        while let Some((_, message)) = self.transport.receive()? {
+            if let SyncMessage::Update { acks, deltas } = message {
+                updates.push((acks, deltas));
+            }
+        }
+        if updates.is_empty() {
+            return Ok(0);
+        }
+
+        while let Some(undo) = self.predictions.pop() {
+            self.simulation.apply_delta(undo);
+        }
+
+        let received = updates.len();
+        for (acks, deltas) in updates {
+            for delta in deltas {
+                self.simulation.apply_delta(delta);
+            }
+            if let Some(&(_, acknowledged)) = acks.iter().find(|(player, _)| *player == self.player) {
+                self.unacknowledged.retain(|&(sequence, _, _)| sequence > acknowledged);
+            }
+        }
+
+        // Applying deltas emits no events, so those of the replayed predictions are the latest.
+        self.simulation.discard_events(self.unacknowledged.iter().map(|&(_, _, events)| events).sum());
+        let mut unacknowledged = std::mem::take(&mut self.unacknowledged);
+        for (_, input, events) in &mut unacknowledged {
+            *events = self.predict_locally(input.clone());
+        }
+        self.unacknowledged = unacknowledged;
+        Ok(received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::LoopbackTransport;
+
+    #[derive(Default)]
+    struct Log {
+        pending: Vec<String>,
+        applied: Vec<String>,
+        events: Vec<String>,
+        issuers: Vec<Issuer>,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum LogDelta {
+        Push(String),
+        Pop,
+    }
+
+    impl Simulation for Log {
+        type Input = String;
+
+        fn enqueue_action(&mut self, input: String) {
+            self.pending.push(input);
+        }
+
+        fn enqueue_action_as(&mut self, issuer: Issuer, input: String) {
+            self.issuers.push(issuer);
+            self.enqueue_action(input);
+        }
+
+        fn process_actions(&mut self) {
+            self.events.extend(self.pending.iter().map(|entry| format!("applied {}", entry)));
+            self.applied.append(&mut self.pending);
+        }
+
+        fn state_hash(&self) -> u64 {
+            crate::simulation::hash_entry("log", &self.applied.len(), &self.applied)
+        }
+    }
+
+    impl Authoritative for Log {
+        type Delta = LogDelta;
+
+        fn process_recording(&mut self) -> Vec<LogDelta> {
+            let deltas = self.pending.iter().cloned().map(LogDelta::Push).collect();
+            self.process_actions();
+            deltas
+        }
+
+        fn process_reversible(&mut self) -> Vec<LogDelta> {
+            let undo = self.pending.iter().map(|_| LogDelta::Pop).collect();
+            self.process_actions();
+            undo
+        }
+
+        fn apply_delta(&mut self, delta: LogDelta) {
+            match delta {
+                LogDelta::Push(entry) => self.applied.push(entry),
+                LogDelta::Pop => {
+                    self.applied.pop();
+                }
+            }
+        }
+
+        fn pending_events(&self) -> usize {
+            self.events.len()
+        }
+
+        fn discard_events(&mut self, count: usize) {
+            self.events.truncate(self.events.len().saturating_sub(count));
+        }
+    }
+
+    #[test]
+    fn clients_reconcile_predictions_with_the_server() {
+        let mut network = LoopbackTransport::network(3).into_iter();
+        let mut server = SyncServer::new(Log::default(), network.next().unwrap(), [(1, 1), (2, 2)]);
+        let mut first = SyncClient::new(Log::default(), network.next().unwrap(), 1);
+        let mut second = SyncClient::new(Log::default(), network.next().unwrap(), 2);
+
+        second.predict("b1".to_string()).unwrap();
+        first.predict("a1".to_string()).unwrap();
+        assert_eq!(first.simulation().applied, vec!["a1"]);
+
+        assert_eq!(server.poll().unwrap(), 2);
+        first.predict("a2".to_string()).unwrap();
+
+        assert_eq!(first.poll().unwrap(), 1);
+        assert_eq!(first.simulation().applied, vec!["b1", "a1", "a2"]);
+        assert_eq!(first.pending_inputs(), 1);
+
+        assert_eq!(second.poll().unwrap(), 1);
+        assert_eq!(second.simulation().applied, vec!["b1", "a1"]);
+        assert_eq!(second.pending_inputs(), 0);
+
+        server.poll().unwrap();
+        first.poll().unwrap();
+        second.poll().unwrap();
+        assert_eq!(first.simulation().applied, server.simulation().applied);
+        assert_eq!(second.simulation().applied, server.simulation().applied);
+        assert_eq!(first.pending_inputs(), 0);
+    }
+
+    #[test]
+    fn replayed_predictions_do_not_emit_events_twice() {
+        let mut network = LoopbackTransport::network(3).into_iter();
+        let mut server = SyncServer::new(Log::default(), network.next().unwrap(), [(1, 1), (2, 2)]);
+        let mut first = SyncClient::new(Log::default(), network.next().unwrap(), 1);
+        let mut second = SyncClient::new(Log::default(), network.next().unwrap(), 2);
+
+        second.predict("b1".to_string()).unwrap();
+        first.predict("a1".to_string()).unwrap();
+        server.poll().unwrap();
+        first.predict("a2".to_string()).unwrap();
+        assert_eq!(first.simulation().events, vec!["applied a1", "applied a2"]);
+
+        // The server applied b1 first, so a2 is rolled back and replayed.
+        first.poll().unwrap();
+        assert_eq!(first.simulation().applied, vec!["b1", "a1", "a2"]);
+        assert_eq!(first.simulation().events, vec!["applied a1", "applied a2"]);
+
+        first.simulation_mut().events.clear();
+        server.poll().unwrap();
+        first.poll().unwrap();
+        assert_eq!(first.simulation().applied, server.simulation().applied);
+        assert!(first.simulation().events.is_empty());
+    }
+
+    #[test]
+    fn servers_take_the_player_from_the_peer() {
+        let mut network = LoopbackTransport::network(3).into_iter();
+        let mut server = SyncServer::new(Log::default(), network.next().unwrap(), [(1, 7)]);
+        let mut client = SyncClient::new(Log::default(), network.next().unwrap(), 7);
+        let mut stranger = network.next().unwrap();
+
+        client.predict("a1".to_string()).unwrap();
+        stranger.send(SyncMessage::Input { sequence: 0, input: "forged".to_string() }).unwrap();
+
+        assert_eq!(server.poll().unwrap(), 1);
+        assert_eq!(server.simulation().applied, vec!["a1"]);
+        assert_eq!(server.simulation().issuers, vec![Issuer::Player(7)]);
+    }
+}
@@ -0,0 +1,53 @@
+/// A line diff from `expected` to `actual`: common lines are prefixed with two spaces,
+/// missing ones with `- ` and unexpected ones with `+ `.
+pub fn diff_lines(expected: &[String], actual: &[String]) -> String {
+    // Longest common subsequence, filled from the end so the walk below goes forward.
+    let mut common = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
+
+/// Formats each value with `Debug`, for `diff_lines`.
+pub fn debug_lines<V: std::fmt::Debug>(values: impl IntoIterator<Item = V>) -> Vec<String> {
+    values.into_iter().map(|value| format!("{:?}", value)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_show_missing_and_unexpected_lines() {
+        let expected = debug_lines(["hit", "heal", "die"]);
+        let actual = debug_lines(["hit", "die", "revive"]);
+
+        assert_eq!(
+            diff_lines(&expected, &actual),
+            "  \"hit\"\n- \"heal\"\n  \"die\"\n+ \"revive\""
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+
+#[derive(Debug)]
+pub enum TransportError {
+    Disconnected,
+    Io(std::io::Error),
+    Encode(String),
+    Decode(String),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Disconnected => write!(f, "peer disconnected"),
+            TransportError::Io(error) => write!(f, "transport I/O error: {}", error),
+            TransportError::Encode(error) => write!(f, "could not encode message: {}", error),
+            TransportError::Decode(error) => write!(f, "could not decode message: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<std::io::Error> for TransportError {
+    fn from(error: std::io::Error) -> Self {
+        TransportError::Io(error)
+    }
+}
+
+/// Identifies a peer of a session, as numbered by its transport.
+pub type PeerId = usize;
+
+/// Broadcasts messages to every other peer of a session.
+pub trait Transport<M> {
+    fn send(&mut self, message: M) -> Result<(), TransportError>;
+
+    /// Returns the next received message and the peer it came from, without blocking.
+    /// The peer is the one the message arrived from rather than anything the message
+    /// claims, so session layers can trust it to tell players apart.
+    fn receive(&mut self) -> Result<Option<(PeerId, M)>, TransportError>;
+}
+
+/// In-process transport over channels, for tests and local play.
+pub struct LoopbackTransport<M> {
+    id: PeerId,
+    peers: Vec<Sender<(PeerId, M)>>,
+    inbox: Receiver<(PeerId, M)>,
+}
+
+impl<M> LoopbackTransport<M> {
+    /// Creates `peers` fully connected transports, numbered from 0 in order.
+    pub fn network(peers: usize) -> Vec<LoopbackTransport<M>> {
+        let (senders, inboxes): (Vec<_>, Vec<_>) = (0..peers).map(|_| channel()).unzip();
+        inboxes
+            .into_iter()
+            .enumerate()
+            .map(|(index, inbox)| LoopbackTransport {
+                id: index,
+                peers: senders
+                    .iter()
+                    .enumerate()
+                    .filter(|&(peer, _)| peer != index)
+                    .map(|(_, sender)| sender.clone())
+                    .collect(),
+                inbox,
+            })
+            .collect()
+    }
+
+    pub fn id(&self) -> PeerId {
+        self.id
+    }
+}
+
+impl<M: Clone> Transport<M> for LoopbackTransport<M> {
+    fn send(&mut self, message: M) -> Result<(), TransportError> {
+        for peer in &self.peers {
+            peer.send((self.id, message.clone())).map_err(|_| TransportError::Disconnected)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<(PeerId, M)>, TransportError> {
+        match self.inbox.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(TransportError::Disconnected),
+        }
+    }
+}
+
+#[cfg(feature = "tcp")]
+pub use tcp::TcpTransport;
+
+#[cfg(feature = "tcp")]
+mod tcp {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+    use std::sync::mpsc::{channel, Receiver, TryRecvError};
+    use std::thread;
+
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    use super::{PeerId, Transport, TransportError};
+
+    /// Newline-delimited JSON over TCP in a star: the host relays every message it
+    /// receives to the other clients, so all peers see every message once.
+    ///
+    /// The host is peer 0 and clients are numbered from 1 in the order they were accepted.
+    /// Clients send bare messages; the host relays them along with the peer of the stream
+    /// they arrived on, so a client can't pass for another.
+    pub struct TcpTransport<M> {
+        streams: Vec<TcpStream>,
+        inbox: Receiver<Result<(PeerId, M), TransportError>>,
+        relay: bool,
+    }
+
+    impl<M: Serialize + DeserializeOwned + Send + 'static> TcpTransport<M> {
+        /// Listens on `address` and waits for `clients` peers to join.
+        pub fn host(address: impl ToSocketAddrs, clients: usize) -> Result<Self, TransportError> {
+            TcpTransport::accept(&TcpListener::bind(address)?, clients)
+        }
+
+        pub fn accept(listener: &TcpListener, clients: usize) -> Result<Self, TransportError> {
+            let streams = (0..clients)
+                .map(|_| listener.accept().map(|(stream, _)| stream))
+                .collect::<Result<Vec<_>, _>>()?;
+            TcpTransport::from_streams(streams, true)
+        }
+
+        pub fn join(address: impl ToSocketAddrs) -> Result<Self, TransportError> {
+            let stream = TcpStream::connect(address)?;
+            TcpTransport::from_streams(vec![stream], false)
+        }
+
+        fn from_streams(streams: Vec<TcpStream>, relay: bool) -> Result<Self, TransportError> {
+            let (sender, inbox) = channel();
+            for (index, stream) in streams.iter().enumerate() {
+                stream.set_nodelay(true)?;
+                let reader = BufReader::new(stream.try_clone()?);
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    for line in reader.lines() {
+                        let message = line.map_err(TransportError::from).and_then(|line| {
+                            let decode = |error: serde_json::Error| TransportError::Decode(error.to_string());
+                            if relay {
+                                serde_json::from_str(&line).map(|message| (index + 1, message)).map_err(decode)
+                            } else {
+                                serde_json::from_str(&line).map_err(decode)
+                            }
+                        });
+                        if sender.send(message).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            Ok(TcpTransport { streams, inbox, relay })
+        }
+
+        fn write_to(&mut self, message: &impl Serialize, skip: Option<usize>) -> Result<(), TransportError> {
+            let mut line = serde_json::to_string(message).map_err(|error| TransportError::Encode(error.to_string()))?;
+            line.push('\n');
+            for (index, stream) in self.streams.iter_mut().enumerate() {
+                if Some(index) != skip {
+                    stream.write_all(line.as_bytes())?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl<M: Serialize + DeserializeOwned + Send + 'static> Transport<M> for TcpTransport<M> {
+        fn send(&mut self, message: M) -> Result<(), TransportError> {
+            if self.relay {
+                self.write_to(&(0, message), None)
+            } else {
+                self.write_to(&message, None)
+            }
+        }
+
+        fn receive(&mut self) -> Result<Option<(PeerId, M)>, TransportError> {
+            match self.inbox.try_recv() {
+                Ok(message) => {
+                    let (origin, message) = message?;
+                    if self.relay && self.streams.len() > 1 {
+                        self.write_to(&(origin, &message), Some(origin - 1))?;
+                    }
+                    Ok(Some((origin, message)))
+                }
+                Err(TryRecvError::Empty) => Ok(None),
+                Err(TryRecvError::Disconnected) => Err(TransportError::Disconnected),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loopback_broadcasts_to_other_peers() {
+        let mut network = LoopbackTransport::network(3);
+        network[0].send("hello").unwrap();
+
+        assert_eq!(network[0].receive().unwrap(), None);
+        assert_eq!(network[1].receive().unwrap(), Some((0, "hello")));
+        assert_eq!(network[2].receive().unwrap(), Some((0, "hello")));
+    }
+
+    #[cfg(feature = "tcp")]
+    #[test]
+    fn tcp_host_relays_between_clients() {
+        use std::net::TcpListener;
+        use std::time::{Duration, Instant};
+
+        fn receive(transport: &mut TcpTransport<String>) -> (PeerId, String) {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            loop {
+                if let Some(message) = transport.receive().unwrap() {
+                    return message;
+                }
+                assert!(Instant::now() < deadline, "no message received");
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let mut first = TcpTransport::join(address).unwrap();
+        let mut second = TcpTransport::join(address).unwrap();
+        let mut host = TcpTransport::accept(&listener, 2).unwrap();
+
+        first.send("from first".to_string()).unwrap();
+        let (from_first, message) = receive(&mut host);
+        assert_eq!(message, "from first");
+        assert_eq!(receive(&mut second), (from_first, "from first".to_string()));
+
+        host.send("from host".to_string()).unwrap();
+        assert_eq!(receive(&mut first), (0, "from host".to_string()));
+        assert_eq!(receive(&mut second), (0, "from host".to_string()));
+
+        second.send("from second".to_string()).unwrap();
+        let (from_second, _) = receive(&mut host);
+        assert_eq!(receive(&mut first), (from_second, "from second".to_string()));
+        assert_ne!(from_first, from_second);
+    }
+}
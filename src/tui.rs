@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+use std::io;
+use std::time::Duration;
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::symbols::Marker;
+use ratatui::text::Line;
+use ratatui::widgets::canvas::{Canvas, Points};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Row, Table};
+use ratatui::Frame;
+
+use crate::inspector::{Inspect, Snapshot};
+
+const RECENT_EVENTS: usize = 200;
+const LABELLED_POINTS: usize = 40;
+
+/// A terminal dashboard over a live simulation: a table per component, the selected
+/// entity, the pending queue with the follow-on actions of the last processed action, the
+/// recent events and a plot of each spatial index.
+///
+/// `run` takes over the terminal and processes one action per tick. `step` and `draw` let
+/// a game that already uses `ratatui` embed the panels in its own loop instead.
+pub struct Dashboard {
+    component: usize,
+    entity: usize,
+    last_action: Option<String>,
+    follow_ons: Vec<String>,
+    events: VecDeque<String>,
+    paused: bool,
+    interval: Duration,
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Dashboard::new()
+    }
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Dashboard {
+            component: 0,
+            entity: 0,
+            last_action: None,
+            follow_ons: Vec::new(),
+            events: VecDeque::new(),
+            paused: false,
+            interval: Duration::from_millis(250),
+        }
+    }
+
+    /// Time between two actions while running.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn paused(mut self, paused: bool) -> Self {
+        self.paused = paused;
+        self
+    }
+
+    /// Processes the next pending action and records what it led to. Returns `false`
+    /// when nothing was pending.
+    pub fn step<W: Inspect>(&mut self, world: &mut W) -> bool {
+        let pending_before = world.pending_actions();
+        let events_before = world.events().len();
+        if !world.process_next_action() {
+            return false;
+        }
+
+        let pending = world.pending_actions();
+        let kept = (pending_before.len() - 1).min(pending.len());
+        self.last_action = pending_before.into_iter().next();
+        self.follow_ons = pending[kept..].to_vec();
+
+        let events = world.events();
+        let new_events = if events.len() >= events_before { &events[events_before..] } else { &events[..] };
+        self.events.extend(new_events.iter().cloned());
+        while self.events.len() > RECENT_EVENTS {
+            self.events.pop_front();
+        }
+        true
+    }
+
+    /// Applies a key press. Returns `false` when the dashboard should close.
+    pub fn handle_key<W: Inspect>(&mut self, key: KeyEvent, world: &mut W) -> bool {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char(' ') => self.paused = !self.paused,
+            KeyCode::Char('n') | KeyCode::Right => {
+                self.step(world);
+            }
+            KeyCode::Tab => self.component = self.component.wrapping_add(1),
+            KeyCode::BackTab => self.component = self.component.wrapping_sub(1),
+            KeyCode::Down => self.entity = self.entity.wrapping_add(1),
+            KeyCode::Up => self.entity = self.entity.wrapping_sub(1),
+            KeyCode::Char('+') => self.interval /= 2,
+            KeyCode::Char('-') => self.interval = (self.interval * 2).min(Duration::from_secs(10)),
+            _ => {}
+        }
+        true
+    }
+
+    /// Takes over the terminal until `q` is pressed. Whenever no action is pending,
+    /// `feed` is called to enqueue more, e.g. the next turn of a scripted battle.
+    pub fn run<W: Inspect>(&mut self, world: &mut W, mut feed: impl FnMut(&mut W)) -> io::Result<()> {
+        let mut terminal = ratatui::init();
+        let result = (|| loop {
+            terminal.draw(|frame| self.draw(frame, world))?;
+            if event::poll(self.interval)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press && !self.handle_key(key, world) {
+                        return Ok(());
+                    }
+                }
+            } else if !self.paused && !self.step(world) {
+                feed(world);
+            }
+        })();
+        ratatui::restore();
+        result
+    }
+
+    pub fn draw<W: Inspect>(&self, frame: &mut Frame, world: &W) {
+        let snapshot = world.snapshot();
+        let entities = snapshot.entities();
+        let selected = (!entities.is_empty()).then(|| entities[self.entity % entities.len()]);
+
+        let [top, middle, bottom, help] = Layout::vertical([
+            Constraint::Percentage(35),
+            Constraint::Percentage(25),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [table, detail] = Layout::horizontal([Constraint::Percentage(60), Constraint::Fill(1)]).areas(top);
+        let [queue, events] = Layout::horizontal([Constraint::Percentage(50), Constraint::Fill(1)]).areas(middle);
+
+        self.draw_component(frame, table, &snapshot, selected);
+        draw_entity(frame, detail, &snapshot, selected);
+        self.draw_queue(frame, queue, world);
+        self.draw_events(frame, events);
+        draw_plots(frame, bottom, world, selected);
+
+        let state = if self.paused { "paused".to_string() } else { format!("every {:?}", self.interval) };
+        frame.render_widget(
+            Line::from(format!(
+                " {} · space pause · n step · tab component · ↑↓ entity · +/- speed · q quit",
+                state
+            ))
+            .dim(),
+            help,
+        );
+    }
+
+    fn draw_component(&self, frame: &mut Frame, area: Rect, snapshot: &Snapshot, selected: Option<&str>) {
+        let names: Vec<&str> = snapshot.components.keys().copied().collect();
+        if names.is_empty() {
+            frame.render_widget(Block::bordered().title("components"), area);
+            return;
+        }
+        let name = names[self.component % names.len()];
+        let rows = snapshot.components[name].iter().map(|(id, value)| {
+            let row = Row::new([id.clone(), value.clone()]);
+            if Some(id.as_str()) == selected {
+                row.reversed()
+            } else {
+                row
+            }
+        });
+        let table = Table::new(rows, [Constraint::Length(12), Constraint::Fill(1)])
+            .header(Row::new(["id", "value"]).bold())
+            .block(Block::bordered().title(format!("{} ({}/{})", name, self.component % names.len() + 1, names.len())));
+        frame.render_widget(table, area);
+    }
+
+    fn draw_queue<W: Inspect>(&self, frame: &mut Frame, area: Rect, world: &W) {
+        let pending = world.pending_actions();
+        let first_follow_on = pending.len().saturating_sub(self.follow_ons.len());
+        let mut items = Vec::new();
+        if let Some(last) = &self.last_action {
+            items.push(ListItem::new(format!("last: {}", last)).dim());
+        }
+        items.extend(pending.iter().enumerate().map(|(index, action)| {
+            if index >= first_follow_on {
+                ListItem::new(format!("↳ {}", action)).fg(Color::Yellow)
+            } else {
+                ListItem::new(action.as_str())
+            }
+        }));
+        frame.render_widget(List::new(items).block(Block::bordered().title(format!("pending ({})", pending.len()))), area);
+    }
+
+    fn draw_events(&self, frame: &mut Frame, area: Rect) {
+        let visible = area.height.saturating_sub(2) as usize;
+        let items: Vec<ListItem> = self
+            .events
+            .iter()
+            .skip(self.events.len().saturating_sub(visible))
+            .map(|event| ListItem::new(event.as_str()))
+            .collect();
+        frame.render_widget(List::new(items).block(Block::bordered().title("recent events")), area);
+    }
+}
+
+fn draw_entity(frame: &mut Frame, area: Rect, snapshot: &Snapshot, selected: Option<&str>) {
+    let Some(id) = selected else {
+        frame.render_widget(Block::bordered().title("entity"), area);
+        return;
+    };
+    let lines: Vec<Line> = snapshot
+        .entity(id)
+        .into_iter()
+        .map(|(component, value)| Line::from(format!("{}: {}", component, value)))
+        .collect();
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(format!("entity {}", id))), area);
+}
+
+fn draw_plots<W: Inspect>(frame: &mut Frame, area: Rect, world: &W, selected: Option<&str>) {
+    let components = world.spatial_components();
+    if components.is_empty() {
+        return;
+    }
+    let areas = Layout::horizontal(components.iter().map(|_| Constraint::Fill(1))).split(area);
+    for (component, &area) in components.iter().zip(areas.iter()) {
+        let points: Vec<(String, (f64, f64))> = world
+            .spatial_points(component)
+            .into_iter()
+            .filter_map(|(id, coordinates)| {
+                let x = *coordinates.first()?;
+                let y = coordinates.get(1).copied().unwrap_or(0.0);
+                (x.is_finite() && y.is_finite()).then_some((id, (x, y)))
+            })
+            .collect();
+        let bounds = |axis: fn(&(f64, f64)) -> f64| {
+            let (min, max) = points
+                .iter()
+                .map(|(_, point)| axis(point))
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| (min.min(value), max.max(value)));
+            if min > max {
+                [-1.0, 1.0]
+            } else {
+                [min - 1.0, max + 1.0]
+            }
+        };
+        let (x_bounds, y_bounds) = (bounds(|point| point.0), bounds(|point| point.1));
+        let coordinates: Vec<(f64, f64)> = points.iter().map(|(_, point)| *point).collect();
+
+        let canvas = Canvas::default()
+            .block(Block::bordered().title(format!("{} ({})", component, points.len())))
+            .marker(Marker::Braille)
+            .x_bounds(x_bounds)
+            .y_bounds(y_bounds)
+            .paint(|ctx| {
+                // Crowded plots only label the selected entity.
+                let labelled = points.len() <= LABELLED_POINTS;
+                if !labelled {
+                    ctx.draw(&Points { coords: &coordinates, color: Color::White });
+                }
+                for (id, (x, y)) in &points {
+                    let highlighted = Some(id.as_str()) == selected;
+                    if highlighted || labelled {
+                        let style = if highlighted { Style::new().fg(Color::Yellow).bold() } else { Style::new() };
+                        ctx.print(*x, *y, Line::styled(id.clone(), style));
+                    }
+                }
+            });
+        frame.render_widget(canvas, area);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    use super::*;
+    use crate::inspector::tests::Counters;
+    use crate::simulation::Simulation;
+
+    #[test]
+    fn the_dashboard_shows_the_last_action_and_its_effects() {
+        let mut world = Counters::default();
+        world.enqueue_action((1, 5));
+        world.enqueue_action((2, 3));
+        let mut dashboard = Dashboard::new().paused(true);
+        assert!(dashboard.step(&mut world));
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| dashboard.draw(frame, &world)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+
+        assert!(screen.contains("counter (1/1)"));
+        assert!(screen.contains("last: (1, 5)"));
+        assert!(screen.contains("pending (1)"));
+        assert!(screen.contains("entity 1"));
+        assert!(screen.contains("paused"));
+        assert_eq!(world.values.get(&2), None);
+    }
+}
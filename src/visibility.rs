@@ -0,0 +1,210 @@
+use std::collections::HashSet;
+
+use crate::grid::{cell_at, wide, GridObject, HashGrid, SquareCell};
+
+/// Cells on the Bresenham line from `from` to `to`, both ends included.
+pub fn line<C: SquareCell>(from: C, to: C) -> Vec<C> {
+    let [mut x, mut y] = wide(from.xy());
+    let [x1, y1] = wide(to.xy());
+    let dx = (x1 - x).abs();
+    let dy = -(y1 - y).abs();
+    let sx = if x < x1 { 1 } else { -1 };
+    let sy = if y < y1 { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    // Every cell between two cells is in range, so none are dropped.
+    let mut cells: Vec<C> = cell_at([x, y]).into_iter().collect();
+    while x != x1 || y != y1 {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += sx;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += sy;
+        }
+        cells.extend(cell_at::<C>([x, y]));
+    }
+    cells
+}
+
+/// Whether `to` can be seen from `from`. Only the cells strictly between the two
+/// are checked with `blocks_sight`, so a wall can be seen but not seen through.
+pub fn has_line_of_sight<C, F>(from: C, to: C, mut blocks_sight: F) -> bool
+where
+    C: SquareCell,
+    F: FnMut(&C) -> bool,
+{
+    let cells = line(from, to);
+    let between = cells.len().saturating_sub(1);
+    cells.iter().take(between).skip(1).all(|cell| !blocks_sight(cell))
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldOfView<C> {
+    pub origin: C,
+    pub radius: u32,
+    visible: HashSet<C>,
+}
+
+impl<C: SquareCell> FieldOfView<C> {
+    pub fn is_visible(&self, cell: &C) -> bool {
+        self.visible.contains(cell)
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = &C> {
+        self.visible.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.visible.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visible.is_empty()
+    }
+
+    pub fn visible_occupants<'a, T>(&self, grid: &'a HashGrid<T>) -> Vec<&'a T::Id>
+    where
+        T: GridObject<Cell = C>,
+    {
+        self.visible.iter().flat_map(|cell| grid.occupants(cell)).collect()
+    }
+
+    /// What `occupants_at` finds in each visible cell, for indexes other than `HashGrid`
+    /// such as the generated `SpatialQuery` ones.
+    pub fn visible_with<I, F>(&self, mut occupants_at: F) -> Vec<I>
+    where
+        F: FnMut(&C) -> Vec<I>,
+    {
+        self.visible.iter().flat_map(&mut occupants_at).collect()
+    }
+}
+
+const OCTANTS: [[i128; 4]; 8] = [
+    [1, 0, 0, 1],
+    [0, 1, 1, 0],
+    [0, -1, 1, 0],
+    [-1, 0, 0, 1],
+    [-1, 0, 0, -1],
+    [0, -1, -1, 0],
+    [0, 1, -1, 0],
+    [1, 0, 0, -1],
+];
+
+/// Every cell visible from `origin` within `radius`, computed with recursive
+/// shadowcasting. Cells that block sight are visible themselves.
+pub fn field_of_view<C, F>(origin: C, radius: u32, mut blocks_sight: F) -> FieldOfView<C>
+where
+    C: SquareCell,
+    F: FnMut(&C) -> bool,
+{
+    let mut visible = HashSet::new();
+    visible.insert(origin);
+    for transform in &OCTANTS {
+        cast_light(wide(origin.xy()), i128::from(radius), 1, 1.0, 0.0, transform, &mut blocks_sight, &mut visible);
+    }
+    FieldOfView { origin, radius, visible }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cast_light<C, F>(
+    origin: [i128; 2],
+    radius: i128,
+    row: i128,
+    mut start_slope: f64,
+    end_slope: f64,
+    transform: &[i128; 4],
+    blocks_sight: &mut F,
+    visible: &mut HashSet<C>,
+) where
+    C: SquareCell,
+    F: FnMut(&C) -> bool,
+{
+    if start_slope < end_slope {
+        return;
+    }
+    let [xx, xy, yx, yy] = *transform;
+
+    for distance in row..=radius {
+        let dy = -distance;
+        let mut blocked = false;
+        let mut next_start_slope = start_slope;
+
+        for dx in -distance..=0 {
+            let left_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+            let right_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+            if start_slope < right_slope {
+                continue;
+            }
+            if end_slope > left_slope {
+                break;
+            }
+
+            // Cells past the edge of the coordinate range are neither visible nor see-through.
+            let cell = cell_at([origin[0] + dx * xx + dy * xy, origin[1] + dx * yx + dy * yy]);
+            if let Some(cell) = cell.filter(|_| dx * dx + dy * dy <= radius * radius) {
+                visible.insert(cell);
+            }
+
+            let opaque = cell.as_ref().is_none_or(&mut *blocks_sight);
+            if blocked {
+                if opaque {
+                    next_start_slope = right_slope;
+                } else {
+                    blocked = false;
+                    start_slope = next_start_slope;
+                }
+            } else if opaque && distance < radius {
+                blocked = true;
+                cast_light(origin, radius, distance + 1, start_slope, left_slope, transform, blocks_sight, visible);
+                next_start_slope = right_slope;
+            }
+        }
+
+        if blocked {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walls_block_line_of_sight() {
+        let wall = |cell: &[i32; 2]| *cell == [2, 0];
+
+        assert_eq!(line([0, 0], [3, 1]), vec![[0, 0], [1, 0], [2, 1], [3, 1]]);
+        assert!(!has_line_of_sight([0, 0], [4, 0], wall));
+        assert!(has_line_of_sight([0, 0], [2, 0], wall));
+        assert!(has_line_of_sight([0, 0], [0, 4], wall));
+    }
+
+    #[test]
+    fn walls_cast_shadows() {
+        let fov = field_of_view([0, 0], 5, |cell: &[i32; 2]| *cell == [2, 0]);
+
+        assert!(fov.is_visible(&[0, 0]));
+        assert!(fov.is_visible(&[2, 0]));
+        assert!(!fov.is_visible(&[4, 0]));
+        assert!(fov.is_visible(&[-4, 0]));
+        assert!(fov.is_visible(&[0, 5]));
+        assert!(!fov.is_visible(&[5, 5]));
+    }
+
+    #[test]
+    fn sight_stops_at_the_edge_of_the_coordinate_range() {
+        let mut checked = Vec::new();
+        let fov = field_of_view([i8::MAX - 1, i8::MIN + 1], 4, |cell: &[i8; 2]| {
+            checked.push(*cell);
+            false
+        });
+
+        assert!(fov.is_visible(&[i8::MAX, i8::MIN]));
+        assert!(fov.cells().all(|cell| cell[0] >= i8::MAX - 5 && cell[1] <= i8::MIN + 5));
+        assert!(checked.iter().all(|cell| cell[0] >= i8::MAX - 5 && cell[1] <= i8::MIN + 5));
+    }
+}
@@ -0,0 +1,112 @@
+mod common;
+
+use common::*;
+use rule_system::actor::Issuer;
+
+// Rejects hits that would kill, and despawns the target instead.
+fn no_overkill(action: &Action, state: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    let future = FutureState { state, action };
+    match action.get_updated_health().keys().find(|&&id| future.get_health(id).is_some_and(|health| health.0 <= 0)) {
+        Some(&id) => (ActionStatus::Reject, RuleStatus::KeepChecking, vec![Act::Despawn(id)]),
+        None => (ActionStatus::Accept, RuleStatus::KeepChecking, vec![]),
+    }
+}
+
+// Lets despawns through without checking the rules after it.
+fn despawns_always_pass(action: &Action, _: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    if action.get_removed_health().is_empty() {
+        (ActionStatus::Accept, RuleStatus::KeepChecking, vec![])
+    } else {
+        (ActionStatus::Accept, RuleStatus::StopChecking, vec![])
+    }
+}
+
+fn accept(_: &Action, _: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    (ActionStatus::Accept, RuleStatus::KeepChecking, vec![])
+}
+
+fn audited_world() -> GameWorld<Act, ()> {
+    let mut world = GameWorld::new(vec![no_overkill, despawns_always_pass, accept], populate, vec![], vec![], vec![]);
+    world.enqueue_action(Act::Spawn { id: 1, at: [0, 0], team: 1 });
+    world.process_actions();
+    world.set_audit_trail(true);
+    world
+}
+
+fn evaluation(rule: usize, action_status: ActionStatus, rule_status: RuleStatus, cut_short: bool, reactions: &[&str]) -> RuleEvaluation {
+    RuleEvaluation {
+        rule,
+        action_status,
+        rule_status,
+        cut_short,
+        reactions: reactions.iter().map(|reaction| reaction.to_string()).collect(),
+    }
+}
+
+#[test]
+fn the_trail_records_every_rule_run_on_each_action() {
+    let mut world = audited_world();
+    world.enqueue_action_as(Issuer::Player(1), Act::Hit(1, 3));
+    world.enqueue_action_as(Issuer::Player(1), Act::Hit(1, 20));
+    world.process_actions();
+
+    let keep = RuleStatus::KeepChecking;
+    assert_eq!(
+        world.audit_trail(),
+        [
+            ActionAudit {
+                input: "Hit(1, 3)".to_string(),
+                issuer: Issuer::Player(1),
+                accepted: true,
+                denied: None,
+                rules: vec![
+                    evaluation(0, ActionStatus::Accept, keep, false, &[]),
+                    evaluation(1, ActionStatus::Accept, keep, false, &[]),
+                    evaluation(2, ActionStatus::Accept, keep, false, &[]),
+                ],
+            },
+            ActionAudit {
+                input: "Hit(1, 20)".to_string(),
+                issuer: Issuer::Player(1),
+                accepted: false,
+                denied: None,
+                rules: vec![
+                    evaluation(0, ActionStatus::Reject, keep, false, &["Despawn(1)"]),
+                    evaluation(1, ActionStatus::Accept, keep, false, &[]),
+                    evaluation(2, ActionStatus::Accept, keep, false, &[]),
+                ],
+            },
+            ActionAudit {
+                input: "Despawn(1)".to_string(),
+                issuer: Issuer::Player(1),
+                accepted: true,
+                denied: None,
+                rules: vec![
+                    evaluation(0, ActionStatus::Accept, keep, false, &[]),
+                    evaluation(1, ActionStatus::Accept, RuleStatus::StopChecking, true, &[]),
+                ],
+            },
+        ]
+    );
+}
+
+#[test]
+fn audits_are_kept_until_taken_and_only_while_enabled() {
+    let mut world = audited_world();
+    world.enqueue_action(Act::Hit(1, 1));
+    world.process_actions();
+
+    assert_eq!(world.take_audit_trail().len(), 1);
+    assert!(world.audit_trail().is_empty());
+
+    world.enqueue_action(Act::Hit(1, 1));
+    world.process_actions();
+    world.set_audit_trail(false);
+    world.set_audit_trail(true);
+    assert!(world.audit_trail().is_empty());
+
+    world.set_audit_trail(false);
+    world.enqueue_action(Act::Hit(1, 1));
+    world.process_actions();
+    assert!(world.take_audit_trail().is_empty());
+}
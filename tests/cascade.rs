@@ -0,0 +1,78 @@
+mod common;
+
+use common::*;
+use rule_system::actor::Issuer;
+use rule_system::cascade::{CascadeNode, Cause};
+
+// Lets despawns through without checking the rules after it.
+fn despawns_always_pass(action: &Action, _: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    if action.get_removed_health().is_empty() {
+        (ActionStatus::Accept, RuleStatus::KeepChecking, vec![])
+    } else {
+        (ActionStatus::Accept, RuleStatus::StopChecking, vec![])
+    }
+}
+
+// Rejects hits that would kill, and despawns the target instead.
+fn no_overkill(action: &Action, state: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    let future = FutureState { state, action };
+    match action.get_updated_health().keys().find(|&&id| future.get_health(id).is_some_and(|health| health.0 <= 0)) {
+        Some(&id) => (ActionStatus::Reject, RuleStatus::KeepChecking, vec![Act::Despawn(id)]),
+        None => (ActionStatus::Accept, RuleStatus::KeepChecking, vec![]),
+    }
+}
+
+fn recorded_world() -> GameWorld<Act, ()> {
+    let mut world = GameWorld::new(vec![despawns_always_pass, no_overkill], populate, vec![], vec![], vec![]);
+    world.enqueue_action(Act::Spawn { id: 1, at: [0, 0], team: 1 });
+    world.process_actions();
+    world.add_permission(|issuer, _, _| if issuer.player() == Some(2) { Err("spectator".to_string()) } else { Ok(()) });
+    world.set_cascade_recording(true);
+    world
+}
+
+fn node(id: u64, input: &str, issuer: Issuer, accepted: bool, rejected_by: Option<usize>, cause: Option<Cause>) -> CascadeNode {
+    CascadeNode { id, input: input.to_string(), issuer, accepted, rejected_by, cause }
+}
+
+#[test]
+fn follow_ons_record_the_rule_that_caused_them() {
+    let mut world = recorded_world();
+    world.enqueue_action(Act::Hit(1, 3));
+    world.enqueue_action_as(Issuer::Player(1), Act::Hit(1, 20));
+    world.enqueue_action_as(Issuer::Player(2), Act::Hit(1, 1));
+    world.process_actions();
+
+    let cascade = world.cascade().unwrap();
+    assert_eq!(
+        cascade.nodes,
+        vec![
+            node(0, "Hit(1, 3)", Issuer::System, true, None, None),
+            node(1, "Hit(1, 20)", Issuer::Player(1), false, Some(1), None),
+            // Denied before any rule ran.
+            node(2, "Hit(1, 1)", Issuer::Player(2), false, None, None),
+            node(3, "Despawn(1)", Issuer::Player(1), true, None, Some(Cause { parent: 1, rule: 1, rule_accepted: false })),
+        ]
+    );
+    assert_eq!(cascade.children(1).map(|node| node.id).collect::<Vec<_>>(), vec![3]);
+    assert_eq!(cascade.roots().map(|node| node.id).collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert_eq!(cascade.depth(), 2);
+    assert_eq!(world.state.get_health(1), None);
+}
+
+#[test]
+fn each_cascade_keeps_counting_node_ids() {
+    let mut world = recorded_world();
+    world.enqueue_action(Act::Hit(1, 3));
+    world.process_actions();
+    world.enqueue_action(Act::Hit(1, 9));
+    world.process_actions();
+
+    let cascade = world.cascade().unwrap();
+    let recorded: Vec<_> = cascade.nodes.iter().map(|node| (node.id, node.input.as_str())).collect();
+    assert_eq!(recorded, vec![(1, "Hit(1, 9)"), (2, "Despawn(1)")]);
+    assert_eq!(cascade.node(2).and_then(|node| node.cause), Some(Cause { parent: 1, rule: 1, rule_accepted: false }));
+
+    world.set_cascade_recording(false);
+    assert!(world.cascade().is_none());
+}
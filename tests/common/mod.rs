@@ -0,0 +1,97 @@
+// Each test file uses part of this world.
+#![allow(dead_code)]
+
+use rule_system::register_components;
+use serde::Serialize;
+
+pub type EntityId = u32;
+pub type Position = [i32; 2];
+pub type Tile = [i32; 2];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Health(pub i32);
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Team(pub u8);
+
+/// A building covering `size` tiles from `origin`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Footprint {
+    pub origin: [i32; 2],
+    pub size: [i32; 2],
+}
+
+impl rstar::RTreeObject for Footprint {
+    type Envelope = rstar::AABB<[i32; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        let [x, y] = self.origin;
+        rstar::AABB::from_corners(self.origin, [x + self.size[0] - 1, y + self.size[1] - 1])
+    }
+}
+
+pub mod world {
+    use rstar::Point;
+
+    use super::*;
+
+    register_components!(
+        index EntityId,
+        components { Health, Team }
+        spatial { Position, Footprint: extent, Tile: grid }
+        indexes {
+            by_team: Team => u8 = |team: &Team| team.0,
+            by_health: Health => i32 = |health: &Health| health.0
+        }
+    );
+}
+
+pub use world::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Act {
+    Spawn { id: EntityId, at: Position, team: u8 },
+    Move(EntityId, Position),
+    Hit(EntityId, i32),
+    Despawn(EntityId),
+}
+
+pub fn populate(input: Act, state: &GameState, action: &mut Action, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) {
+    match input {
+        Act::Spawn { id, at, team } => {
+            action.insert_position(id, at);
+            action.insert_tile(id, at);
+            action.insert_health(id, Health(10));
+            action.insert_team(id, Team(team));
+        }
+        Act::Move(id, to) => {
+            action.insert_position(id, to);
+            action.insert_tile(id, to);
+        }
+        Act::Hit(id, damage) => {
+            if let Some(health) = state.get_health(id) {
+                action.insert_health(id, Health(health.0 - damage));
+            }
+        }
+        Act::Despawn(id) => action.remove_all(id),
+    }
+}
+
+pub fn world<E>() -> GameWorld<Act, E> {
+    GameWorld::new(vec![], populate, vec![], vec![], vec![])
+}
+
+/// A world with `spawns` processed.
+pub fn world_with<E>(spawns: &[(EntityId, Position, u8)]) -> GameWorld<Act, E> {
+    let mut world = world();
+    for &(id, at, team) in spawns {
+        world.enqueue_action(Act::Spawn { id, at, team });
+    }
+    world.process_actions();
+    world
+}
+
+pub fn sorted<I: Ord>(mut ids: Vec<I>) -> Vec<I> {
+    ids.sort();
+    ids
+}
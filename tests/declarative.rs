@@ -0,0 +1,80 @@
+#![cfg(feature = "declarative")]
+
+mod common;
+
+use common::*;
+use rule_system::declarative::{DeclarativeError, RuleSet};
+
+const RULES: &str = r#"
+    [[rule]]
+    name = "stay on the map"
+    component = "position"
+    check = { InBounds = { min = [0, 0], max = [9, 9] } }
+
+    [[rule]]
+    name = "walk one tile"
+    component = "position"
+    check = { MaxStep = { max = 1 } }
+
+    [[rule]]
+    name = "one unit per tile"
+    component = "tile"
+    check = "Unoccupied"
+"#;
+
+#[test]
+fn toml_rules_judge_actions() {
+    let rules = RuleSet::from_toml(RULES).unwrap();
+    let mut world = world_with::<()>(&[(1, [0, 0], 0), (2, [2, 0], 0)]);
+    world.add_declarative_rules(&rules).unwrap();
+
+    world.enqueue_action(Act::Move(1, [1, 0]));
+    world.process_actions();
+    assert_eq!(world.state.get_position(1), Some(&[1, 0]));
+
+    world.enqueue_action(Act::Move(1, [3, 0]));
+    world.enqueue_action(Act::Move(1, [2, 0]));
+    world.enqueue_action(Act::Move(2, [2, -1]));
+    world.process_actions();
+    assert_eq!(world.state.get_position(1), Some(&[1, 0]));
+    assert_eq!(world.state.get_position(2), Some(&[2, 0]));
+    assert!(rules.take_errors().is_empty());
+}
+
+#[test]
+fn ron_rules_judge_actions() {
+    let rules = RuleSet::from_ron(r#"(rule: [(name: "alive", component: "health", check: AtLeast(min: 1))])"#).unwrap();
+    let mut world = world_with::<()>(&[(1, [0, 0], 0)]);
+    world.add_declarative_rules(&rules).unwrap();
+
+    world.enqueue_action(Act::Hit(1, 4));
+    world.enqueue_action(Act::Hit(1, 6));
+    world.process_actions();
+    assert_eq!(world.state.get_health(1), Some(&Health(6)));
+}
+
+#[test]
+fn rules_that_cannot_be_checked_record_an_error() {
+    let rules = RuleSet::from_ron(r#"(rule: [(name: "morale", component: "team", check: AtLeast(field: Some("morale"), min: 0))])"#).unwrap();
+    let mut world = world_with::<()>(&[]);
+    world.add_declarative_rules(&rules).unwrap();
+
+    world.enqueue_action(Act::Spawn { id: 1, at: [0, 0], team: 1 });
+    world.process_actions();
+
+    assert_eq!(world.state.get_team(1), None);
+    let errors = rules.take_errors();
+    assert!(matches!(&errors[..], [DeclarativeError::Check { rule, .. }] if rule == "morale"), "{:?}", errors);
+    assert!(rules.take_errors().is_empty());
+}
+
+#[test]
+fn unknown_components_fail_at_load_time() {
+    let rules = RuleSet::from_ron(r#"(rule: [(name: "mana", component: "mana", check: AtLeast(min: 0))])"#).unwrap();
+    let mut world = world::<()>();
+
+    assert!(matches!(
+        world.add_declarative_rules(&rules),
+        Err(DeclarativeError::UnknownComponent { component, .. }) if component == "mana"
+    ));
+}
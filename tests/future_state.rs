@@ -0,0 +1,71 @@
+mod common;
+
+use common::*;
+use rstar::AABB;
+
+// 1 at (0, 0) moves to (5, 5), 2 at (1, 0) is removed, 3 at (2, 0) stays, 4 appears at
+// (0, 1) and 5 is inserted and removed in the same action.
+fn pending() -> Action {
+    let mut action = Action::new();
+    action.insert_position(1, [5, 5]);
+    action.remove_position(2);
+    action.insert_position(4, [0, 1]);
+    action.insert_position(5, [0, 0]);
+    action.remove_position(5);
+    action
+}
+
+fn spawned() -> GameWorld<Act, ()> {
+    world_with(&[(1, [0, 0], 0), (2, [1, 0], 0), (3, [2, 0], 0)])
+}
+
+#[test]
+fn get_sees_updates_and_removals() {
+    let world = spawned();
+    let action = pending();
+    let future = FutureState { state: &world.state, action: &action };
+
+    assert_eq!(future.get_position(1), Some(&[5, 5]));
+    assert_eq!(future.get_position(2), None);
+    assert_eq!(future.get_position(3), Some(&[2, 0]));
+    assert_eq!(future.get_position(5), None);
+    assert_eq!(future.get_health(2), Some(&Health(10)));
+}
+
+#[test]
+fn entities_within_sees_the_pending_action() {
+    let world = spawned();
+    let action = pending();
+    let future = FutureState { state: &world.state, action: &action };
+
+    assert_eq!(sorted(future.entities_within_position(&world.spatial_position, &[0, 0], 2)), vec![3, 4]);
+    assert_eq!(sorted(future.entities_within_position(&world.spatial_position, &[5, 5], 0)), vec![1]);
+    assert_eq!(sorted(world.entities_within_position(&[0, 0], 2)), vec![1, 2, 3]);
+}
+
+#[test]
+fn nearest_sees_the_pending_action() {
+    let world = spawned();
+    let action = pending();
+    let future = FutureState { state: &world.state, action: &action };
+
+    assert_eq!(future.nearest_n_position(&world.spatial_position, &[0, 0], 2), vec![4, 3]);
+    assert_eq!(future.nearest_n_position(&world.spatial_position, &[0, 0], 9), vec![4, 3, 1]);
+    assert_eq!(future.nearest_n_position(&world.spatial_position, &[6, 6], 1), vec![1]);
+}
+
+#[test]
+fn entities_at_sees_the_pending_action() {
+    let world = spawned();
+    let action = pending();
+    let future = FutureState { state: &world.state, action: &action };
+
+    assert_eq!(future.entities_at_position(&world.spatial_position, &[0, 0]), Vec::<EntityId>::new());
+    assert_eq!(future.entity_at_position(&world.spatial_position, &[1, 0]), None);
+    assert_eq!(future.entity_at_position(&world.spatial_position, &[0, 1]), Some(4));
+    assert_eq!(future.entity_at_position(&world.spatial_position, &[2, 0]), Some(3));
+    assert_eq!(
+        sorted(future.entities_in_rect_position(&world.spatial_position, &AABB::from_corners([0, 0], [5, 5]))),
+        vec![1, 3, 4]
+    );
+}
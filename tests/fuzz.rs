@@ -0,0 +1,66 @@
+mod common;
+
+use common::*;
+use rule_system::fuzz::Fuzzer;
+
+// Nothing stops a unit from being hit below zero, so this breaks sooner or later.
+fn health_is_positive(state: &GameState) -> Result<(), String> {
+    match state.health.iter().find(|(_, health)| health.0 <= 0) {
+        Some((id, health)) => Err(format!("{} has {:?}", id, health)),
+        None => Ok(()),
+    }
+}
+
+fn fuzzed() -> GameWorld<Act, ()> {
+    let mut world = world_with(&[(1, [0, 0], 1), (2, [5, 5], 2)]);
+    world.add_invariant("health is positive", health_is_positive);
+    world
+}
+
+fn damage(actions: &[Act]) -> i32 {
+    actions
+        .iter()
+        .map(|action| match action {
+            Act::Hit(_, damage) => *damage,
+            _ => 0,
+        })
+        .sum()
+}
+
+#[test]
+fn the_fuzzer_finds_and_shrinks_a_broken_invariant() {
+    let failure = Fuzzer::new(fuzzed, |rng| match rng.below(3) {
+        0 => Act::Move(rng.range(1..3) as u32, [rng.range(-5..5) as i32, rng.range(-5..5) as i32]),
+        _ => Act::Hit(rng.range(1..3) as u32, rng.range(1..4) as i32),
+    })
+    .seed(7)
+    .steps(40)
+    .run()
+    .unwrap_err();
+
+    assert!(failure.message.contains("health is positive"), "{}", failure.message);
+    assert!(failure.actions.len() < failure.generated, "{}", failure);
+
+    // Only the hits on the unit that went down are left.
+    let target = match failure.actions[0] {
+        Act::Hit(id, _) => id,
+        ref other => panic!("{:?} was not shrunk away", other),
+    };
+    assert!(failure.actions.iter().all(|action| matches!(action, Act::Hit(id, _) if *id == target)), "{}", failure);
+    assert!(damage(&failure.actions) >= 10, "{}", failure);
+
+    // And none of them can be dropped.
+    for index in 0..failure.actions.len() {
+        let mut fewer = failure.actions.clone();
+        fewer.remove(index);
+        assert!(damage(&fewer) < 10, "{}", failure);
+    }
+}
+
+#[test]
+fn the_fuzzer_passes_when_the_invariant_holds() {
+    let mut fuzzer = Fuzzer::new(fuzzed, |rng| Act::Move(rng.range(1..3) as u32, [rng.range(-5..5) as i32, 0]))
+        .runs(10)
+        .steps(20);
+    assert!(fuzzer.run().is_ok());
+}
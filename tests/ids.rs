@@ -0,0 +1,103 @@
+//! Ids that are neither `Copy` nor a plain integer.
+
+use std::collections::VecDeque;
+
+pub type Position = [i32; 2];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Health(pub i32);
+
+mod battle {
+    use rstar::Point;
+
+    use super::*;
+
+    rule_system::register_components!(
+        index (u32, String),
+        components { Health }
+        spatial { Position }
+        indexes { by_health: Health => i32 = |health: &Health| health.0 }
+    );
+}
+
+use battle::*;
+
+type UnitId = (u32, String);
+
+fn unit(battle: u32, name: &str) -> UnitId {
+    (battle, name.to_string())
+}
+
+#[derive(Debug, Clone)]
+enum Act {
+    Spawn(UnitId, Position),
+    Move(UnitId, Position),
+    Despawn(UnitId),
+}
+
+fn populate(input: Act, _: &GameState, action: &mut Action, _: &PositionIndex) {
+    match input {
+        Act::Spawn(id, at) => {
+            action.insert_health(id.clone(), Health(3));
+            action.insert_position(id, at);
+        }
+        Act::Move(id, to) => action.insert_position(id, to),
+        Act::Despawn(id) => action.remove_all(id),
+    }
+}
+
+fn stay_on_the_map(action: &Action, _: &GameState, _: &PositionIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    if action.get_updated_position().values().all(|&[x, y]| x >= 0 && y >= 0) {
+        (ActionStatus::Accept, RuleStatus::KeepChecking, Vec::new())
+    } else {
+        (ActionStatus::Reject, RuleStatus::KeepChecking, Vec::new())
+    }
+}
+
+fn report_moves(events: &mut VecDeque<UnitId>, action: &Action, _: &GameState, _: &PositionIndex) {
+    events.extend(action.get_updated_position().keys().cloned());
+}
+
+#[test]
+fn composite_ids_run_through_the_pipeline() {
+    let mut world: GameWorld<Act, UnitId> = GameWorld::new(vec![stay_on_the_map], populate, vec![report_moves], vec![], vec![]);
+    world.enqueue_action(Act::Spawn(unit(1, "archer"), [0, 0]));
+    world.enqueue_action(Act::Spawn(unit(2, "archer"), [3, 0]));
+    world.enqueue_action(Act::Move(unit(1, "archer"), [-1, 0]));
+    world.enqueue_action(Act::Move(unit(1, "archer"), [1, 1]));
+    world.process_actions();
+
+    assert_eq!(world.state.get_position(unit(1, "archer")), Some(&[1, 1]));
+    assert_eq!(world.entity_at_position(&[3, 0]), Some(unit(2, "archer")));
+    assert_eq!(world.nearest_n_position(&[0, 0], 1), vec![unit(1, "archer")]);
+    assert_eq!(world.state.by_health(&3).count(), 2);
+    assert_eq!(world.events_queue, [unit(1, "archer"), unit(2, "archer"), unit(1, "archer")]);
+    assert_eq!(world.verify_spatial_indexes(), Ok(()));
+
+    world.enqueue_action(Act::Despawn(unit(2, "archer")));
+    world.process_actions();
+    assert_eq!(world.state.get_health(unit(2, "archer")), None);
+    assert_eq!(world.entities_within_position(&[0, 0], 10), vec![unit(1, "archer")]);
+    assert_eq!(world.verify_spatial_indexes(), Ok(()));
+}
+
+#[test]
+fn composite_ids_work_with_future_state_and_undo() {
+    let mut world: GameWorld<Act, UnitId> = GameWorld::new(vec![], populate, vec![], vec![], vec![]);
+    world.enqueue_action(Act::Spawn(unit(1, "knight"), [0, 0]));
+    world.process_actions();
+
+    let mut action = Action::new();
+    action.insert_position(unit(1, "scout"), [1, 0]);
+    action.remove_all(unit(1, "knight"));
+    let future = FutureState { state: &world.state, action: &action };
+    assert_eq!(future.entities_within_position(&world.spatial_position, &[0, 0], 2), vec![unit(1, "scout")]);
+    assert_eq!(future.get_health(unit(1, "knight")), None);
+
+    let undo = world.apply_action_reversible(action);
+    assert_eq!(world.state.get_position(unit(1, "knight")), None);
+    world.apply_action(undo);
+    assert_eq!(world.state.get_position(unit(1, "knight")), Some(&[0, 0]));
+    assert_eq!(world.state.get_position(unit(1, "scout")), None);
+    assert_eq!(world.verify_spatial_indexes(), Ok(()));
+}
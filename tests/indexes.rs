@@ -0,0 +1,74 @@
+mod common;
+
+use common::*;
+
+fn team(world: &GameWorld<Act, ()>, team: u8) -> Vec<EntityId> {
+    sorted(world.state.by_team(&team).copied().collect())
+}
+
+#[test]
+fn indexes_follow_inserts_rekeys_and_removals() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0), (2, [1, 0], 0), (3, [2, 0], 1)]);
+    assert_eq!(team(&world, 0), vec![1, 2]);
+    assert_eq!(team(&world, 1), vec![3]);
+
+    let mut action = Action::new();
+    action.insert_team(2, Team(1));
+    world.apply_action(action);
+    assert_eq!(team(&world, 0), vec![1]);
+    assert_eq!(team(&world, 1), vec![2, 3]);
+
+    world.enqueue_action(Act::Despawn(3));
+    world.enqueue_action(Act::Hit(1, 4));
+    world.process_actions();
+    assert_eq!(team(&world, 1), vec![2]);
+    assert_eq!(sorted(world.state.by_health(&6).copied().collect()), vec![1]);
+    assert_eq!(sorted(world.state.by_health(&10).copied().collect()), vec![2]);
+
+    world.state.clear();
+    assert_eq!(team(&world, 0), Vec::<EntityId>::new());
+    assert_eq!(world.state.by_health(&10).count(), 0);
+}
+
+#[test]
+fn updating_and_removing_in_one_action_leaves_no_entry() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0)]);
+
+    let mut action = Action::new();
+    action.insert_team(1, Team(2));
+    action.insert_team(4, Team(2));
+    action.remove_team(1);
+    action.remove_team(4);
+    world.apply_action(action);
+
+    assert_eq!(team(&world, 0), Vec::<EntityId>::new());
+    assert_eq!(team(&world, 2), Vec::<EntityId>::new());
+}
+
+#[test]
+fn rebuilding_matches_incremental_maintenance() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0), (2, [1, 0], 1)]);
+    world.state.team.insert(1, Team(1));
+    assert_eq!(team(&world, 1), vec![2]);
+
+    world.state.rebuild_indexes();
+    assert_eq!(team(&world, 0), Vec::<EntityId>::new());
+    assert_eq!(team(&world, 1), vec![1, 2]);
+}
+
+#[test]
+fn future_state_sees_the_action_through_indexes() {
+    let world = world_with::<()>(&[(1, [0, 0], 0), (2, [1, 0], 0), (3, [2, 0], 1)]);
+
+    let mut action = Action::new();
+    action.insert_team(1, Team(1));
+    action.insert_team(4, Team(0));
+    action.remove_team(2);
+    action.insert_team(5, Team(0));
+    action.remove_team(5);
+    let future = FutureState { state: &world.state, action: &action };
+
+    assert_eq!(sorted(future.by_team(&0)), vec![4]);
+    assert_eq!(sorted(future.by_team(&1)), vec![1, 3]);
+    assert_eq!(team(&world, 0), vec![1, 2]);
+}
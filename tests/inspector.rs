@@ -0,0 +1,113 @@
+mod common;
+
+use common::*;
+use rule_system::inspector::{Inspect, Inspector};
+
+fn parse(_: &str) -> Result<Act, String> {
+    Err("actions can't be parsed here".to_string())
+}
+
+fn inspected() -> GameWorld<Act, ()> {
+    let mut world = world_with(&[(1, [0, 0], 1), (2, [3, 0], 1), (3, [10, 10], 2)]);
+    let mut action = Action::new();
+    action.insert_footprint(
+        4,
+        Footprint {
+            origin: [2, 2],
+            size: [2, 2],
+        },
+    );
+    world.apply_action(action);
+    world
+}
+
+fn query(world: &mut GameWorld<Act, ()>, line: &str) -> Result<String, String> {
+    Inspector::new(parse)
+        .execute(world, line)
+        .map(Option::unwrap_or_default)
+}
+
+#[test]
+fn queries_run_on_the_generated_indexes() {
+    let mut world = inspected();
+
+    assert_eq!(
+        query(&mut world, "query position within 0 0 3").map(|ids| sorted_lines(&ids)),
+        Ok("1\n2".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query position nearest 9 9 1"),
+        Ok("3".to_string())
+    );
+    assert_eq!(query(&mut world, "query tile at 3 0"), Ok("2".to_string()));
+    assert_eq!(
+        query(&mut world, "query tile rect -1 -1 4 4").map(|ids| sorted_lines(&ids)),
+        Ok("1\n2".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query footprint at 3 3"),
+        Ok("4".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query footprint rect 0 0 1 1"),
+        Ok(String::new())
+    );
+
+    assert_eq!(
+        world.spatial_components(),
+        ["position", "footprint", "tile"]
+    );
+    assert_eq!(
+        world.spatial_points("footprint"),
+        [("4".to_string(), vec![2.0, 2.0])]
+    );
+    assert!(world.spatial_points("health").is_empty());
+}
+
+#[test]
+fn bad_queries_are_reported() {
+    let mut world = inspected();
+
+    assert_eq!(
+        query(&mut world, "query position within 0 0"),
+        Err("`within` takes 3 arguments".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query tile rect 0 0 1"),
+        Err("`rect` takes 4 arguments".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query footprint at 1"),
+        Err("`at` takes 2 arguments".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query position at 0 x"),
+        Err("`x` is not a number".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query tile within 0 0 1.5"),
+        Err("`1.5` is not a number".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query position nearest 0 0 -1"),
+        Err("`-1` is not a count".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query position around 0 0"),
+        Err("unknown query `around`, expected within, nearest, at or rect".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query health at 0 0"),
+        Err("`health` is not a spatial component".to_string())
+    );
+    assert_eq!(
+        query(&mut world, "query position"),
+        Err("usage: query <position|footprint|tile> <query> <args>".to_string())
+    );
+}
+
+fn sorted_lines(text: &str) -> String {
+    let mut lines: Vec<&str> = text.lines().collect();
+    lines.sort_unstable();
+    lines.join("\n")
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::*;
+
+fn health_is_positive(state: &GameState) -> Result<(), String> {
+    match state.health.iter().find(|(_, health)| health.0 <= 0) {
+        Some((id, health)) => Err(format!("{} has {:?}", id, health)),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn check_invariants_reports_the_broken_invariant() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0)]);
+    world.add_invariant("health is positive", health_is_positive);
+    assert_eq!(world.check_invariants(), Ok(()));
+
+    world.state.health.insert(1, Health(0));
+    assert_eq!(world.check_invariants(), Err(("health is positive", "1 has Health(0)".to_string())));
+}
+
+#[test]
+fn invariants_hold_across_accepted_actions() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0)]);
+    world.add_invariant("health is positive", health_is_positive);
+
+    world.enqueue_action(Act::Hit(1, 9));
+    world.enqueue_action(Act::Move(1, [1, 1]));
+    world.process_actions();
+
+    assert_eq!(world.state.get_health(1), Some(&Health(1)));
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "Invariant `health is positive` broken by action Hit(1, 10): 1 has Health(0)\nGameState before committing was: GameState {")]
+fn processing_panics_when_an_invariant_breaks() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0)]);
+    world.add_invariant("health is positive", health_is_positive);
+
+    world.enqueue_action(Act::Hit(1, 10));
+    world.process_actions();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "Invariant `health is positive` broken by action")]
+fn applying_panics_when_an_invariant_breaks() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0)]);
+    world.add_invariant("health is positive", health_is_positive);
+
+    let mut action = Action::new();
+    action.insert_health(1, Health(-1));
+    world.apply_action(action);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+fn the_panic_shows_the_state_before_and_after() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0)]);
+    world.add_invariant("health is positive", health_is_positive);
+    world.enqueue_action(Act::Hit(1, 10));
+
+    let panic = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| world.process_actions())).unwrap_err();
+    let message = panic.downcast_ref::<String>().unwrap();
+    let compact: String = message.split_whitespace().collect();
+    let (before, after) = compact.split_once("GameStateaftercommittingis:").unwrap();
+
+    assert!(before.contains("health:{1:Health(10,),}"), "{}", message);
+    assert!(after.contains("health:{1:Health(0,),}"), "{}", message);
+    assert_eq!(world.state.get_health(1), Some(&Health(0)));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::time::Duration;
+
+use common::*;
+use rule_system::lockstep::{LockstepMessage, LockstepSession, SessionError};
+use rule_system::simulation::Simulation;
+use rule_system::transport::LoopbackTransport;
+
+type Session = LockstepSession<GameWorld<Act, ()>, LoopbackTransport<LockstepMessage<Act>>>;
+
+fn sessions() -> Vec<Session> {
+    LoopbackTransport::network(2)
+        .into_iter()
+        .zip([(1, (1, 2)), (2, (0, 1))])
+        .map(|(transport, (player, peer))| {
+            let world = world_with(&[(1, [0, 0], 1), (2, [5, 5], 2)]);
+            LockstepSession::new(world, transport, player, [peer])
+        })
+        .collect()
+}
+
+fn play_turn(sessions: &mut [Session], inputs: [Vec<Act>; 2]) -> Result<(), SessionError> {
+    for (session, inputs) in sessions.iter_mut().zip(inputs) {
+        for input in inputs {
+            session.submit(input);
+        }
+        session.end_turn()?;
+    }
+    for session in sessions.iter_mut() {
+        session.wait_for_turn(Duration::from_secs(1))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn worlds_stay_in_sync_over_several_turns() {
+    let mut sessions = sessions();
+
+    play_turn(&mut sessions, [vec![Act::Move(1, [1, 0]), Act::Hit(2, 3)], vec![Act::Move(2, [4, 5])]]).unwrap();
+    play_turn(&mut sessions, [vec![], vec![Act::Hit(1, 4), Act::Spawn { id: 3, at: [2, 2], team: 2 }]]).unwrap();
+    play_turn(&mut sessions, [vec![Act::Despawn(3)], vec![Act::Move(3, [3, 3])]]).unwrap();
+    for session in &mut sessions {
+        session.poll().unwrap();
+    }
+
+    let [first, second] = [&sessions[0], &sessions[1]].map(|session| session.simulation());
+    assert_eq!(first.state_hash(), second.state_hash());
+    assert_eq!(first.state.get_position(1), Some(&[1, 0]));
+    assert_eq!(first.state.get_health(1), Some(&Health(6)));
+    assert_eq!(first.state.get_health(2), Some(&Health(7)));
+    // Player 1's despawn is processed before player 2's move, which then re-adds a position.
+    assert_eq!(first.state.get_health(3), None);
+    assert_eq!(second.state.get_position(3), Some(&[3, 3]));
+    assert_eq!(sessions[0].turn(), 3);
+}
+
+#[test]
+fn diverging_worlds_are_detected() {
+    let mut sessions = sessions();
+    play_turn(&mut sessions, [vec![Act::Hit(2, 1)], vec![]]).unwrap();
+
+    let mut cheat = Action::new();
+    cheat.insert_health(1, Health(99));
+    sessions[1].simulation_mut().apply_action(cheat);
+    play_turn(&mut sessions, [vec![], vec![Act::Hit(1, 1)]]).unwrap();
+
+    assert!(matches!(sessions[0].poll(), Err(SessionError::Desync { turn: 1, player: 2, .. })));
+    assert!(matches!(sessions[1].poll(), Err(SessionError::Desync { turn: 1, player: 1, .. })));
+}
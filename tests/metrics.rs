@@ -0,0 +1,84 @@
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use common::*;
+use rule_system::actor::Issuer;
+
+// Rejects hits that would kill, and despawns the target instead.
+fn no_overkill(action: &Action, state: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    let future = FutureState { state, action };
+    match action.get_updated_health().keys().find(|&&id| future.get_health(id).is_some_and(|health| health.0 <= 0)) {
+        Some(&id) => (ActionStatus::Reject, RuleStatus::KeepChecking, vec![Act::Despawn(id)]),
+        None => (ActionStatus::Accept, RuleStatus::KeepChecking, vec![]),
+    }
+}
+
+// Lets despawns through without checking the rules after it.
+fn despawns_always_pass(action: &Action, _: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    if action.get_removed_health().is_empty() {
+        (ActionStatus::Accept, RuleStatus::KeepChecking, vec![])
+    } else {
+        (ActionStatus::Accept, RuleStatus::StopChecking, vec![])
+    }
+}
+
+fn slow(_: &Action, _: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    thread::sleep(Duration::from_millis(2));
+    (ActionStatus::Accept, RuleStatus::KeepChecking, vec![])
+}
+
+fn timed_world() -> GameWorld<Act, ()> {
+    let mut world = GameWorld::new(vec![no_overkill, despawns_always_pass, slow], populate, vec![], vec![], vec![]);
+    world.apply_action({
+        let mut action = Action::new();
+        action.insert_health(1, Health(10));
+        action.insert_team(1, Team(1));
+        action
+    });
+    world.add_permission(|issuer, _, _| if issuer.player() == Some(2) { Err("spectator".to_string()) } else { Ok(()) });
+    world.set_metrics(true);
+    world
+}
+
+#[test]
+fn every_step_of_accepted_and_rejected_actions_is_timed() {
+    let mut world = timed_world();
+    world.enqueue_action(Act::Hit(1, 3));
+    world.enqueue_action(Act::Hit(1, 20));
+    world.process_actions();
+
+    let metrics = world.metrics().unwrap();
+    // The rejected hit is followed by the despawn, which stops before the slow rule.
+    assert_eq!(metrics.actions.calls, 3);
+    assert_eq!(metrics.populate.calls, 3);
+    assert_eq!(metrics.rules.iter().map(|timing| timing.calls).collect::<Vec<_>>(), vec![3, 3, 2]);
+    assert_eq!(metrics.hooks_on_accepted.calls, 2);
+    assert_eq!(metrics.hooks_on_rejected.calls, 1);
+    assert_eq!(metrics.hooks_after_commit.calls, 3);
+    assert_eq!(metrics.spatial_updates.calls, 2);
+
+    assert!(metrics.rules[2].total >= Duration::from_millis(4));
+    assert!(metrics.rules[2].max >= Duration::from_millis(2));
+    assert!(metrics.actions.total >= metrics.rules[2].total);
+    assert_eq!(metrics.slowest_rule().map(|(index, _)| index), Some(2));
+}
+
+#[test]
+fn denied_actions_are_timed_without_running_any_step() {
+    let mut world = timed_world();
+    world.enqueue_action_as(Issuer::Player(2), Act::Hit(1, 3));
+    world.process_actions();
+
+    let metrics = world.take_metrics().unwrap();
+    assert_eq!(metrics.actions.calls, 1);
+    assert_eq!(metrics.populate.calls, 0);
+    assert!(metrics.rules.is_empty());
+    assert_eq!(world.metrics().unwrap().actions.calls, 0);
+
+    world.set_metrics(false);
+    world.enqueue_action(Act::Hit(1, 3));
+    world.process_actions();
+    assert!(world.metrics().is_none());
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::*;
+use rule_system::actor::Issuer;
+use rule_system::sync::{SyncMessage, SyncServer};
+use rule_system::transport::{LoopbackTransport, Transport};
+
+// Players may only act on the units of the team with their id.
+fn own_units_only(issuer: &Issuer, input: &Act, state: &GameState) -> Result<(), String> {
+    let target = match input {
+        Act::Spawn { .. } => return Err("only the game spawns units".to_string()),
+        Act::Move(id, _) | Act::Hit(id, _) | Act::Despawn(id) => *id,
+    };
+    let owner = state.get_team(target).map(|team| team.0 as u32);
+    if issuer.controls(owner) {
+        Ok(())
+    } else {
+        Err(format!("unit {} is not yours", target))
+    }
+}
+
+fn guarded_world() -> GameWorld<Act, ()> {
+    let mut world = world_with(&[(1, [0, 0], 1), (2, [5, 5], 2)]);
+    world.add_permission(own_units_only);
+    world
+}
+
+fn denials(world: &GameWorld<Act, ()>) -> Vec<(String, Option<String>)> {
+    world.audit_trail().iter().map(|audit| (audit.input.clone(), audit.denied.clone())).collect()
+}
+
+#[test]
+fn players_may_only_do_what_their_permissions_allow() {
+    let mut world = guarded_world();
+    world.set_audit_trail(true);
+
+    world.enqueue_action_as(Issuer::Player(1), Act::Move(1, [1, 0]));
+    world.enqueue_action_as(Issuer::Player(1), Act::Hit(2, 5));
+    world.enqueue_action_as(Issuer::Ai(2), Act::Spawn { id: 3, at: [9, 9], team: 2 });
+    world.process_actions();
+
+    assert_eq!(world.state.get_position(1), Some(&[1, 0]));
+    assert_eq!(world.state.get_health(2), Some(&Health(10)));
+    assert_eq!(world.state.get_team(3), None);
+    assert_eq!(
+        denials(&world),
+        [
+            ("Move(1, [1, 0])".to_string(), None),
+            ("Hit(2, 5)".to_string(), Some("unit 2 is not yours".to_string())),
+            ("Spawn { id: 3, at: [9, 9], team: 2 }".to_string(), Some("only the game spawns units".to_string())),
+        ]
+    );
+    assert!(world.audit_trail()[1..].iter().all(|audit| !audit.accepted && audit.rules.is_empty()));
+}
+
+#[test]
+fn permissions_see_the_actions_processed_before() {
+    let mut world = guarded_world();
+    world.set_audit_trail(true);
+
+    // Unit 1 changes hands to player 2 before the players' inputs run.
+    world.enqueue_action(Act::Spawn { id: 1, at: [0, 0], team: 2 });
+    world.enqueue_action_as(Issuer::Player(1), Act::Move(1, [1, 0]));
+    world.enqueue_action_as(Issuer::Player(2), Act::Hit(1, 4));
+    world.process_actions();
+
+    assert_eq!(world.state.get_position(1), Some(&[0, 0]));
+    assert_eq!(world.state.get_health(1), Some(&Health(6)));
+    assert_eq!(denials(&world)[1], ("Move(1, [1, 0])".to_string(), Some("unit 1 is not yours".to_string())));
+}
+
+#[test]
+fn the_system_is_not_checked() {
+    let mut world = guarded_world();
+
+    world.enqueue_action_as(Issuer::System, Act::Hit(2, 5));
+    world.enqueue_action(Act::Spawn { id: 3, at: [9, 9], team: 0 });
+    world.process_actions();
+
+    assert_eq!(world.state.get_health(2), Some(&Health(5)));
+    assert_eq!(world.state.get_team(3), Some(&Team(0)));
+}
+
+#[test]
+fn servers_check_inputs_as_the_player_of_their_peer() {
+    let mut network = LoopbackTransport::network(2).into_iter();
+    let mut server = SyncServer::new(guarded_world(), network.next().unwrap(), [(1, 1)]);
+    let mut client = network.next().unwrap();
+
+    client.send(SyncMessage::Input { sequence: 0, input: Act::Hit(1, 3) }).unwrap();
+    client.send(SyncMessage::Input { sequence: 1, input: Act::Hit(2, 3) }).unwrap();
+    assert_eq!(server.poll().unwrap(), 2);
+
+    assert_eq!(server.simulation().state.get_health(1), Some(&Health(7)));
+    assert_eq!(server.simulation().state.get_health(2), Some(&Health(10)));
+}
@@ -0,0 +1,76 @@
+mod common;
+
+use std::collections::VecDeque;
+
+use common::*;
+use rule_system::region::{Rect, RegionTransition};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Crossed {
+    region: usize,
+    transition: RegionTransition,
+    id: EntityId,
+}
+
+fn crossed(events: &mut VecDeque<Crossed>, region: usize, transition: RegionTransition, id: &EntityId, _: &GameState) {
+    events.push_back(Crossed { region, transition, id: *id });
+}
+
+#[test]
+fn game_worlds_can_move_between_threads() {
+    fn assert_send<W: Send>(_: &W) {}
+
+    let mut world = world::<Crossed>();
+    world.add_position_region(Rect { min: [0, 0], max: [1, 1] }, crossed);
+    assert_send(&world);
+}
+
+#[test]
+fn moving_in_and_out_of_regions_calls_their_hooks() {
+    let mut world = world::<Crossed>();
+    let base = world.add_position_region(Rect { min: [0, 0], max: [2, 2] }, crossed);
+    let east = world.add_tile_region(|tile: &[i32; 2]| tile[0] >= 5, crossed);
+    assert_eq!((base, east), (0, 0));
+
+    world.enqueue_action(Act::Spawn { id: 1, at: [1, 1], team: 0 });
+    world.enqueue_action(Act::Move(1, [2, 2]));
+    world.enqueue_action(Act::Move(1, [6, 2]));
+    world.enqueue_action(Act::Despawn(1));
+    world.process_actions();
+
+    let enter = RegionTransition::Enter;
+    let exit = RegionTransition::Exit;
+    assert_eq!(
+        Vec::from(world.events_queue.clone()),
+        vec![
+            Crossed { region: base, transition: enter, id: 1 },
+            Crossed { region: base, transition: exit, id: 1 },
+            Crossed { region: east, transition: enter, id: 1 },
+            Crossed { region: east, transition: exit, id: 1 },
+        ]
+    );
+}
+
+#[test]
+fn hooks_receive_the_region_they_belong_to() {
+    let mut world = world::<Crossed>();
+    world.add_position_region(Rect { min: [0, 0], max: [0, 0] }, crossed);
+    let second = world.add_position_region(Rect { min: [5, 5], max: [9, 9] }, crossed);
+
+    world.enqueue_action(Act::Spawn { id: 1, at: [7, 7], team: 0 });
+    world.process_actions();
+
+    assert_eq!(world.events_queue, [Crossed { region: second, transition: RegionTransition::Enter, id: 1 }]);
+}
+
+#[test]
+fn applied_actions_do_not_trigger_regions() {
+    let mut world = world::<Crossed>();
+    world.add_position_region(Rect { min: [0, 0], max: [2, 2] }, crossed);
+
+    let mut action = Action::new();
+    action.insert_position(1, [1, 1]);
+    world.apply_action(action);
+
+    assert!(world.events_queue.is_empty());
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use std::collections::VecDeque;
+
+use common::*;
+use rule_system::actor::Issuer;
+
+// Rejects hits that would kill, and despawns the target instead.
+fn no_overkill(action: &Action, state: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    let future = FutureState { state, action };
+    match action.get_updated_health().keys().find(|&&id| future.get_health(id).is_some_and(|health| health.0 <= 0)) {
+        Some(&id) => (ActionStatus::Reject, RuleStatus::KeepChecking, vec![Act::Despawn(id)]),
+        None => (ActionStatus::Accept, RuleStatus::KeepChecking, vec![]),
+    }
+}
+
+fn announce_despawns(events: &mut VecDeque<String>, action: &Action, _: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) {
+    for id in action.get_removed_health() {
+        events.push_back(format!("{} despawned", id));
+    }
+}
+
+fn scenario() -> Scenario<Act, ()> {
+    Scenario::with_rules(vec![no_overkill], populate).given(|action| {
+        action.insert_health(1, Health(10));
+        action.insert_team(1, Team(1));
+        action.insert_position(1, [0, 0]);
+    })
+}
+
+#[test]
+fn given_sets_up_the_state_without_running_rules() {
+    Scenario::<Act, ()>::with_rules(vec![no_overkill], populate)
+        .given(|action| action.insert_health(1, Health(-5)))
+        .then()
+        .expect_verdicts(&[])
+        .expect_follow_ons(&[])
+        .expect_health(1, Health(-5));
+}
+
+#[test]
+fn accepted_actions_change_the_state() {
+    let outcome = scenario()
+        .when(Act::Hit(1, 3))
+        .when_as(Issuer::Player(1), Act::Move(1, [2, 1]))
+        .then()
+        .expect_accepted()
+        .expect_follow_ons(&[])
+        .expect_health(1, Health(7))
+        .expect_position(1, [2, 1])
+        .expect_no_footprint(1);
+
+    assert_eq!(outcome.audits()[1].issuer, Issuer::Player(1));
+    assert_eq!(outcome.world().state.get_tile(1), Some(&[2, 1]));
+}
+
+#[test]
+fn rejected_actions_report_their_follow_ons() {
+    scenario()
+        .when(Act::Hit(1, 20))
+        .then()
+        .expect_rejected()
+        .expect_follow_ons(&[Act::Despawn(1)])
+        .expect_no_health(1)
+        .expect_no_position(1);
+}
+
+#[test]
+fn verdicts_are_checked_in_order() {
+    scenario().when(Act::Hit(1, 20)).when(Act::Hit(1, 2)).then().expect_verdicts(&[false, true]);
+}
+
+#[test]
+fn events_come_from_the_world_hooks() {
+    let world = GameWorld::new(vec![no_overkill], populate, vec![announce_despawns], vec![], vec![]);
+    Scenario::new(world)
+        .given(|action| action.insert_health(1, Health(1)))
+        .when(Act::Hit(1, 1))
+        .then()
+        .expect_events(&["1 despawned".to_string()]);
+}
+
+#[test]
+#[should_panic(expected = "unexpected health of 1:\n- Some(Health(5))\n+ Some(Health(7))")]
+fn wrong_components_fail_with_both_values() {
+    scenario().when(Act::Hit(1, 3)).then().expect_health(1, Health(5));
+}
+
+#[test]
+#[should_panic(expected = "unexpected position of 1:\n- None\n+ Some([0, 0])")]
+fn unexpected_components_fail() {
+    scenario().then().expect_no_position(1);
+}
+
+#[test]
+#[should_panic(expected = "unexpected verdicts:\n- accepted\n+ rejected\n\nactions:\n  Hit(1, 20) by system: rejected\n    rule 0: Reject, KeepChecking, reactions [Despawn(1)]\n  Despawn(1) by system: accepted\n    rule 0: Accept, KeepChecking\n\nchanges:\n  - health 1: Health(10)")]
+fn wrong_verdicts_fail_with_the_rules_run_and_the_changes() {
+    scenario().when(Act::Hit(1, 20)).then().expect_accepted();
+}
+
+#[test]
+#[should_panic(expected = "unexpected follow-on actions:\n- Hit(1, 1)\n+ Despawn(1)")]
+fn wrong_follow_ons_fail_with_a_diff() {
+    scenario().when(Act::Hit(1, 20)).then().expect_follow_ons(&[Act::Hit(1, 1)]);
+}
+
+#[test]
+#[should_panic(expected = "unexpected events:\n+ \"1 despawned\"")]
+fn unexpected_events_fail_with_a_diff() {
+    let world = GameWorld::new(vec![no_overkill], populate, vec![announce_despawns], vec![], vec![]);
+    Scenario::new(world).given(|action| action.insert_health(1, Health(1))).when(Act::Hit(1, 1)).then().expect_events(&[]);
+}
+
+#[test]
+#[should_panic(expected = "unexpected verdicts:\n- accepted\n+ rejected\n\nactions:\n  Hit(1, 1) by player 2: rejected\n    denied: not yours")]
+fn denied_actions_are_rejected_without_running_rules() {
+    let mut world = world::<()>();
+    world.add_permission(|_, _, _| Err("not yours".to_string()));
+    Scenario::new(world).when_as(Issuer::Player(2), Act::Hit(1, 1)).then().expect_accepted();
+}
@@ -0,0 +1,158 @@
+mod common;
+
+use common::*;
+use rstar::AABB;
+
+fn spawned() -> GameWorld<Act, ()> {
+    world_with(&[(1, [0, 0], 0), (2, [1, 0], 0), (3, [2, 0], 0)])
+}
+
+#[test]
+fn extents_are_indexed_by_their_envelope() {
+    let mut world = spawned();
+    let mut action = Action::new();
+    action.insert_footprint(10, Footprint { origin: [0, 0], size: [3, 2] });
+    action.insert_footprint(11, Footprint { origin: [10, 10], size: [1, 1] });
+    world.apply_action(action);
+
+    assert_eq!(world.entities_at_footprint(&[2, 1]), vec![10]);
+    assert_eq!(world.entity_at_footprint(&[3, 1]), None);
+    assert_eq!(world.entities_intersecting_footprint(&AABB::from_corners([2, 1], [4, 4])), vec![10]);
+    assert_eq!(world.entities_in_rect_footprint(&AABB::from_corners([2, 1], [4, 4])), Vec::<EntityId>::new());
+    assert_eq!(sorted(world.entities_in_rect_footprint(&AABB::from_corners([0, 0], [10, 10]))), vec![10, 11]);
+    assert_eq!(world.nearest_n_footprint(&[4, 4], 1), vec![10]);
+    assert_eq!(world.entities_within_footprint(&[4, 1], 2), vec![10]);
+}
+
+#[test]
+fn future_extents_are_queried_by_their_whole_footprint() {
+    let mut world = spawned();
+    let mut action = Action::new();
+    action.insert_footprint(10, Footprint { origin: [0, 0], size: [3, 2] });
+    action.insert_footprint(11, Footprint { origin: [10, 10], size: [1, 1] });
+    world.apply_action(action);
+
+    let mut action = Action::new();
+    action.insert_footprint(12, Footprint { origin: [2, 1], size: [4, 4] });
+    action.remove_footprint(11);
+    let future = FutureState { state: &world.state, action: &action };
+
+    assert_eq!(sorted(future.entities_at_footprint(&world.spatial_footprint, &[2, 1])), vec![10, 12]);
+    assert_eq!(sorted(future.entities_within_footprint(&world.spatial_footprint, &[12, 12], 3)), Vec::<EntityId>::new());
+    assert_eq!(
+        sorted(future.entities_intersecting_footprint(&world.spatial_footprint, &AABB::from_corners([5, 4], [9, 9]))),
+        vec![12]
+    );
+    assert_eq!(
+        sorted(future.entities_in_rect_footprint(&world.spatial_footprint, &AABB::from_corners([0, 0], [3, 3]))),
+        vec![10]
+    );
+    assert_eq!(future.nearest_n_footprint(&world.spatial_footprint, &[6, 6], 1), vec![12]);
+}
+
+#[test]
+fn grid_components_are_queried_by_cell() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0), (2, [2, 1], 0), (3, [5, 5], 0), (4, [-3, 0], 0)]);
+    world.enqueue_action(Act::Spawn { id: 5, at: [2, 1], team: 1 });
+    world.process_actions();
+
+    assert_eq!(sorted(world.entities_at_tile(&[2, 1])), vec![2, 5]);
+    assert_eq!(world.entity_at_tile(&[1, 1]), None);
+    assert_eq!(sorted(world.entities_within_tile(&[0, 0], 3)), vec![1, 2, 4, 5]);
+    assert_eq!(sorted(world.entities_in_rect_tile(&AABB::from_corners([0, 0], [5, 5]))), vec![1, 2, 3, 5]);
+    assert_eq!(sorted(world.entities_intersecting_tile(&AABB::from_corners([-100, -100], [0, 100]))), vec![1, 4]);
+    assert_eq!(world.nearest_n_tile(&[0, 0], 1), vec![1]);
+    assert_eq!(sorted(world.nearest_n_tile(&[4, 4], 3)), vec![2, 3, 5]);
+    assert_eq!(world.nearest_n_tile(&[0, 0], 9).len(), 5);
+
+    world.enqueue_action(Act::Move(1, [9, 9]));
+    world.enqueue_action(Act::Despawn(2));
+    world.process_actions();
+    assert_eq!(world.entities_at_tile(&[2, 1]), vec![5]);
+    assert_eq!(world.nearest_n_tile(&[10, 10], 1), vec![1]);
+    assert_eq!(world.verify_spatial_indexes(), Ok(()));
+}
+
+#[test]
+fn future_grid_queries_see_the_pending_action() {
+    let world = world_with::<()>(&[(1, [0, 0], 0), (2, [1, 0], 0)]);
+    let mut action = Action::new();
+    action.insert_tile(1, [3, 3]);
+    action.remove_tile(2);
+    action.insert_tile(3, [0, 1]);
+    let future = FutureState { state: &world.state, action: &action };
+
+    assert_eq!(future.entities_at_tile(&world.spatial_tile, &[0, 0]), Vec::<EntityId>::new());
+    assert_eq!(future.entity_at_tile(&world.spatial_tile, &[3, 3]), Some(1));
+    assert_eq!(sorted(future.entities_within_tile(&world.spatial_tile, &[0, 0], 1)), vec![3]);
+    assert_eq!(future.nearest_n_tile(&world.spatial_tile, &[0, 0], 2), vec![3, 1]);
+}
+
+#[test]
+fn grid_neighbors_come_from_the_index() {
+    use rule_system::grid::Connectivity;
+
+    let world = world_with::<()>(&[(1, [0, 0], 0), (2, [1, 2], 0)]);
+
+    assert_eq!(world.spatial_tile.occupied_neighbors(&[0, 1], Connectivity::Four), vec![([0, 0], &1)]);
+    assert_eq!(world.spatial_tile.free_neighbors(&[0, 1], Connectivity::Eight).len(), 6);
+}
+
+#[test]
+fn initial_states_are_bulk_loaded() {
+    let mut state = GameState::new();
+    for id in 0..50 {
+        state.position.insert(id, [id as i32, 0]);
+        state.tile.insert(id, [0, id as i32]);
+        state.footprint.insert(id, Footprint { origin: [id as i32, id as i32], size: [2, 2] });
+    }
+    let world: GameWorld<Act, ()> = GameWorld::new_with_initial_state(vec![], populate, vec![], vec![], vec![], state);
+
+    assert_eq!(world.verify_spatial_indexes(), Ok(()));
+    assert_eq!(world.spatial_position.size(), 50);
+    assert_eq!(world.spatial_tile.size(), 50);
+    assert_eq!(sorted(world.entities_at_footprint(&[10, 10])), vec![9, 10]);
+    assert_eq!(world.entity_at_tile(&[0, 49]), Some(49));
+}
+
+#[test]
+fn verify_finds_drift_and_rebuild_repairs_it() {
+    let mut world = world_with::<()>(&[(1, [0, 0], 0), (2, [1, 0], 0)]);
+    assert_eq!(world.verify_spatial_indexes(), Ok(()));
+
+    world.state.position.insert(1, [7, 7]);
+    assert_eq!(
+        world.verify_spatial_indexes(),
+        Err("spatial_position is missing PositionTreeObject { index: [7, 7], entity_at: 1 }".to_string())
+    );
+    world.rebuild_spatial_indexes();
+    assert_eq!(world.verify_spatial_indexes(), Ok(()));
+    assert_eq!(world.entity_at_position(&[7, 7]), Some(1));
+
+    world.spatial_tile.insert(TileTreeObject { index: [3, 3], entity_at: 9 });
+    assert_eq!(
+        world.verify_spatial_indexes(),
+        Err("spatial_tile holds 3 entries but GameState has 2".to_string())
+    );
+    world.rebuild_spatial_indexes();
+    assert_eq!(world.verify_spatial_indexes(), Ok(()));
+    assert_eq!(world.entity_at_tile(&[3, 3]), None);
+}
+
+#[test]
+fn fields_of_view_find_entities_in_every_index() {
+    use rule_system::visibility::field_of_view;
+
+    let mut world = world_with::<()>(&[(1, [1, 0], 0), (2, [4, 0], 0), (3, [0, 3], 0)]);
+    let mut action = Action::new();
+    action.insert_footprint(10, Footprint { origin: [2, 0], size: [1, 2] });
+    action.insert_footprint(11, Footprint { origin: [-2, -2], size: [2, 2] });
+    world.apply_action(action);
+
+    let fov = field_of_view([0, 0], 5, |cell| !world.entities_at_footprint(cell).is_empty());
+
+    assert_eq!(sorted(world.spatial_position.entities_visible(&fov)), vec![1, 3]);
+    assert_eq!(sorted(world.spatial_tile.entities_visible(&fov)), vec![1, 3]);
+    assert_eq!(sorted(world.spatial_footprint.entities_visible(&fov)), vec![10, 11]);
+    assert_eq!(sorted(fov.visible_occupants(&world.spatial_tile).into_iter().copied().collect()), vec![1, 3]);
+}
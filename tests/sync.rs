@@ -0,0 +1,68 @@
+mod common;
+
+use std::collections::VecDeque;
+
+use common::*;
+use rule_system::actor::Issuer;
+use rule_system::simulation::Simulation;
+use rule_system::sync::{SyncClient, SyncServer};
+use rule_system::transport::LoopbackTransport;
+
+// Players may only act on the units of the team with their id.
+fn own_units_only(issuer: &Issuer, input: &Act, state: &GameState) -> Result<(), String> {
+    let target = match input {
+        Act::Spawn { .. } => return Err("only the game spawns units".to_string()),
+        Act::Move(id, _) | Act::Hit(id, _) | Act::Despawn(id) => *id,
+    };
+    if issuer.controls(state.get_team(target).map(|team| team.0 as u32)) {
+        Ok(())
+    } else {
+        Err(format!("unit {} is not yours", target))
+    }
+}
+
+fn announce(events: &mut VecDeque<String>, action: &Action, _: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) {
+    let mut changed: Vec<_> = action.get_updated_health().keys().chain(action.get_updated_position().keys()).collect();
+    changed.sort();
+    events.push_back(format!("changed {:?}", changed));
+}
+
+fn world() -> GameWorld<Act, String> {
+    let mut world = GameWorld::new(vec![], populate, vec![announce], vec![], vec![]);
+    for (id, at, team) in [(1, [0, 0], 1), (2, [5, 5], 2)] {
+        world.enqueue_action(Act::Spawn { id, at, team });
+    }
+    world.process_actions();
+    world.events_queue.clear();
+    world
+}
+
+#[test]
+fn clients_converge_on_the_server_after_a_rejected_prediction() {
+    let mut network = LoopbackTransport::network(2).into_iter();
+    let mut server_world = world();
+    server_world.add_permission(own_units_only);
+    let mut server = SyncServer::new(server_world, network.next().unwrap(), [(1, 1)]);
+    // The client doesn't know the permissions, so it predicts the hit on the enemy unit.
+    let mut client = SyncClient::new(world(), network.next().unwrap(), 1);
+
+    client.predict(Act::Hit(2, 5)).unwrap();
+    assert_eq!(client.simulation().state.get_health(2), Some(&Health(5)));
+    assert_eq!(server.poll().unwrap(), 1);
+
+    client.predict(Act::Move(1, [1, 1])).unwrap();
+    assert_eq!(client.poll().unwrap(), 1);
+    assert_eq!(client.pending_inputs(), 1);
+    assert_eq!(client.simulation().state.get_health(2), Some(&Health(10)));
+    assert_eq!(client.simulation().state.get_position(1), Some(&[1, 1]));
+    assert_eq!(client.simulation().events_queue, ["changed [2]", "changed [1]"]);
+
+    assert_eq!(server.poll().unwrap(), 1);
+    assert_eq!(client.poll().unwrap(), 1);
+    assert_eq!(client.pending_inputs(), 0);
+    assert_eq!(client.simulation().state_hash(), server.simulation().state_hash());
+    assert_eq!(client.simulation().state.get_tile(1), Some(&[1, 1]));
+    assert_eq!(client.simulation().entity_at_position(&[1, 1]), Some(1));
+    assert_eq!(client.simulation().verify_spatial_indexes(), Ok(()));
+    assert_eq!(client.simulation().events_queue, ["changed [2]", "changed [1]"]);
+}
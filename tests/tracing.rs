@@ -0,0 +1,129 @@
+mod common;
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use common::*;
+use rule_system::tracing::field::{Field, Visit};
+use rule_system::tracing::span::{Attributes, Id, Record};
+use rule_system::tracing::{self, Event, Metadata, Subscriber};
+
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    name: &'static str,
+    fields: BTreeMap<&'static str, String>,
+    parent: Option<usize>,
+}
+
+#[derive(Default)]
+struct Recorded {
+    spans: Vec<Span>,
+    entered: Vec<usize>,
+}
+
+/// Keeps every span with its fields and the span it was created in.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Recorded>>);
+
+struct Fields<'a>(&'a mut BTreeMap<&'static str, String>);
+
+impl Visit for Fields<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let mut recorded = self.0.lock().unwrap();
+        let mut span = Span {
+            name: attributes.metadata().name(),
+            fields: BTreeMap::new(),
+            parent: recorded.entered.last().copied(),
+        };
+        attributes.record(&mut Fields(&mut span.fields));
+        recorded.spans.push(span);
+        Id::from_u64(recorded.spans.len() as u64)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut recorded = self.0.lock().unwrap();
+        values.record(&mut Fields(&mut recorded.spans[span.into_u64() as usize - 1].fields));
+    }
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, _: &Event<'_>) {}
+
+    fn enter(&self, span: &Id) {
+        self.0.lock().unwrap().entered.push(span.into_u64() as usize - 1);
+    }
+
+    fn exit(&self, _: &Id) {
+        self.0.lock().unwrap().entered.pop();
+    }
+}
+
+impl Recorder {
+    fn spans(&self) -> Vec<Span> {
+        self.0.lock().unwrap().spans.clone()
+    }
+}
+
+fn span(name: &'static str, parent: Option<usize>, fields: &[(&'static str, &str)]) -> Span {
+    Span {
+        name,
+        fields: fields.iter().map(|&(field, value)| (field, value.to_string())).collect(),
+        parent,
+    }
+}
+
+// Rejects hits that would kill, and despawns the target instead.
+fn no_overkill(action: &Action, state: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) -> (ActionStatus, RuleStatus, Vec<Act>) {
+    let future = FutureState { state, action };
+    match action.get_updated_health().keys().find(|&&id| future.get_health(id).is_some_and(|health| health.0 <= 0)) {
+        Some(&id) => (ActionStatus::Reject, RuleStatus::KeepChecking, vec![Act::Despawn(id)]),
+        None => (ActionStatus::Accept, RuleStatus::KeepChecking, vec![]),
+    }
+}
+
+fn announce(events: &mut VecDeque<String>, action: &Action, _: &GameState, _: &PositionIndex, _: &FootprintIndex, _: &TileIndex) {
+    events.push_back(format!("{} changed", action.issuer()));
+}
+
+#[test]
+fn processing_reports_action_rule_and_hook_spans() {
+    let recorder = Recorder::default();
+    let mut world: GameWorld<Act, String> = GameWorld::new(vec![no_overkill], populate, vec![announce], vec![], vec![]);
+    world.enqueue_action(Act::Spawn { id: 1, at: [0, 0], team: 0 });
+    world.process_actions();
+
+    tracing::subscriber::with_default(recorder.clone(), || {
+        world.enqueue_action(Act::Hit(1, 3));
+        world.enqueue_action(Act::Hit(1, 20));
+        world.process_actions();
+    });
+
+    assert_eq!(
+        recorder.spans(),
+        vec![
+            span("action", None, &[("input", "Hit(1, 3)"), ("issuer", "system"), ("accepted", "true"), ("follow_ons", "0")]),
+            span("rule", Some(0), &[("index", "0"), ("verdict", "Accept"), ("stop", "false"), ("follow_ons", "0")]),
+            span("hook", Some(0), &[("on", "accepted"), ("index", "0")]),
+            span("action", None, &[("input", "Hit(1, 20)"), ("issuer", "system"), ("accepted", "false"), ("follow_ons", "1")]),
+            span("rule", Some(3), &[("index", "0"), ("verdict", "Reject"), ("stop", "false"), ("follow_ons", "1")]),
+            span("action", None, &[("input", "Despawn(1)"), ("issuer", "system"), ("accepted", "true"), ("follow_ons", "0")]),
+            span("rule", Some(5), &[("index", "0"), ("verdict", "Accept"), ("stop", "false"), ("follow_ons", "0")]),
+            span("hook", Some(5), &[("on", "accepted"), ("index", "0")]),
+        ]
+    );
+}